@@ -7,9 +7,9 @@ use g1_common::{
     naive_solve::naive_solve_selfcontained,
     nameless::NamelessQuery,
     query::{Clause, Query},
-    Connection, Hash,
+    Connection, Hash, Mutation, MutationResult, TagValue,
 };
-use g1_sqlite_connection::SqliteConnection;
+use g1_sqlite_connection::{BackupOptions, ImportKind, SqliteConnection};
 use linefeed::{Interface, ReadResult};
 use std::{
     collections::BTreeSet,
@@ -31,6 +31,31 @@ struct Args {
     subcommand: Subcommand,
 }
 
+/// Concurrency tuning shared by every subcommand that opens an SQLite connection -- see
+/// `g1_sqlite_connection::SqliteOptions`.
+#[derive(Debug, structopt::StructOpt)]
+struct ConnectOptions {
+    /// How long to retry against a locked database before giving up, in milliseconds. Worth
+    /// raising above the default of 0 whenever more than one process might touch the same store.
+    #[structopt(long = "busy-timeout", default_value = "0")]
+    busy_timeout_ms: u64,
+
+    /// Uses WAL journal mode instead of SQLite's default rollback journal, letting readers and a
+    /// writer touch the database concurrently instead of queueing behind each other.
+    #[structopt(long = "wal")]
+    wal: bool,
+}
+
+impl From<ConnectOptions> for g1_sqlite_connection::SqliteOptions {
+    fn from(opts: ConnectOptions) -> g1_sqlite_connection::SqliteOptions {
+        g1_sqlite_connection::SqliteOptions {
+            busy_timeout: std::time::Duration::from_millis(opts.busy_timeout_ms),
+            foreign_keys: false,
+            wal: opts.wal,
+        }
+    }
+}
+
 #[derive(Debug, structopt::StructOpt)]
 enum Subcommand {
     /// Runs a REPL using an SQLite connection.
@@ -38,6 +63,9 @@ enum Subcommand {
         /// The path to the directory containing the SQLite database and blobs.
         #[structopt(short = "D", long = "db")]
         db_dir: PathBuf,
+
+        #[structopt(flatten)]
+        connect: ConnectOptions,
     },
 
     /// Runs a query using an SQLite connection.
@@ -46,6 +74,13 @@ enum Subcommand {
         #[structopt(short = "D", long = "db")]
         db_dir: PathBuf,
 
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
+        /// How to print the solutions.
+        #[structopt(short = "f", long = "format", default_value = "table")]
+        format: OutputFormat,
+
         /// The path to the file containing the query.
         query_path: Option<PathBuf>,
     },
@@ -56,6 +91,9 @@ enum Subcommand {
         #[structopt(short = "D", long = "db")]
         db_dir: PathBuf,
 
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
         /// The hash to fetch.
         hash: Hash,
     },
@@ -66,12 +104,77 @@ enum Subcommand {
         #[structopt(short = "D", long = "db")]
         db_dir: PathBuf,
 
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
         /// The path to the blob.
         path: Option<PathBuf>,
     },
 
+    /// Takes a hot backup of a live SQLite database (including its blob store), without stopping
+    /// writers.
+    BackupSqlite {
+        /// The path to the directory containing the SQLite database and blobs.
+        #[structopt(short = "D", long = "db")]
+        db_dir: PathBuf,
+
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
+        /// The directory to write the backup to.
+        dest: PathBuf,
+    },
+
+    /// Exports the whole store -- atoms, names, edges, tags, blob metadata, and blob payloads --
+    /// into a single portable SQLite file.
+    ExportSqlite {
+        /// The path to the directory containing the SQLite database and blobs.
+        #[structopt(short = "D", long = "db")]
+        db_dir: PathBuf,
+
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
+        /// The path to write the portable export to.
+        out_file: PathBuf,
+    },
+
+    /// Reconstitutes a store from a file produced by `ExportSqlite`.
+    ImportSqlite {
+        /// The (not yet existing) directory to create the database and blobs in.
+        #[structopt(short = "D", long = "db")]
+        db_dir: PathBuf,
+
+        /// The path to the portable export to import.
+        in_file: PathBuf,
+    },
+
+    /// Bulk-loads atoms, names, edges, or tags from a CSV (or, given a `.tsv` path, TSV) file.
+    ImportCsv {
+        /// The path to the directory containing the SQLite database and blobs.
+        #[structopt(short = "D", long = "db")]
+        db_dir: PathBuf,
+
+        #[structopt(flatten)]
+        connect: ConnectOptions,
+
+        /// The record shape each row is: `atom` (one column, an external key), `name`
+        /// (`atom,ns,title`), `edge` (`from,to,label`), or `tag` (`atom,key,value`). Every column
+        /// named `atom`, `from`, or `to` is an external key, not a literal `Atom` -- the same key
+        /// always resolves to the same newly- or previously-created atom within one import.
+        #[structopt(short = "k", long = "kind")]
+        kind: ImportKind,
+
+        /// The path to the delimited file. Reads stdin if omitted.
+        path: Option<PathBuf>,
+    },
+
     /// Runs a query without access to the database.
     RunSelfContained {
+        /// How to print the solutions.
+        #[structopt(short = "f", long = "format", default_value = "table")]
+        format: OutputFormat,
+
         /// The path to the file containing the query.
         path: Option<PathBuf>,
     },
@@ -92,34 +195,40 @@ fn main(args: Args) -> Result<()> {
     })?;
 
     match args.subcommand {
-        Subcommand::ReplSqlite { db_dir } => tokio::runtime::Builder::new()
+        Subcommand::ReplSqlite { db_dir, connect } => tokio::runtime::Builder::new()
             .enable_all()
             .threaded_scheduler()
             .build()?
             .block_on(async move {
-                let conn = SqliteConnection::open(db_dir).await?;
+                let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
                 repl(conn).await
             }),
-        Subcommand::RunSqlite { db_dir, query_path } => {
+        Subcommand::RunSqlite {
+            db_dir,
+            connect,
+            query_path,
+            format,
+        } => {
             let query = load_query(query_path)?;
+            let columns = goal_column_names(&query);
             let solns = tokio::runtime::Builder::new()
                 .enable_all()
                 .threaded_scheduler()
                 .build()?
                 .block_on(async move {
-                    let conn = SqliteConnection::open(db_dir).await?;
+                    let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
                     conn.query(None, &query).await
                 })?;
-            print_solns(&solns);
+            print_solns(&columns, &solns, format);
             Ok(())
         }
-        Subcommand::FetchBlobSqlite { db_dir, hash } => {
+        Subcommand::FetchBlobSqlite { db_dir, connect, hash } => {
             let contents = tokio::runtime::Builder::new()
                 .enable_all()
                 .threaded_scheduler()
                 .build()?
                 .block_on(async move {
-                    let conn = SqliteConnection::open(db_dir).await?;
+                    let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
                     conn.fetch_blob(hash)
                         .await?
                         .map_ok(|b: bytes::Bytes| BytesMut::from(b.as_ref()))
@@ -129,14 +238,14 @@ fn main(args: Args) -> Result<()> {
             std::io::stdout().write_all(contents.as_ref())?;
             Ok(())
         }
-        Subcommand::StoreBlobSqlite { db_dir, path } => {
+        Subcommand::StoreBlobSqlite { db_dir, connect, path } => {
             let contents = load_file(path)?;
             tokio::runtime::Builder::new()
                 .enable_all()
                 .threaded_scheduler()
                 .build()?
                 .block_on(async move {
-                    let conn = SqliteConnection::open(db_dir).await?;
+                    let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
                     let hash = conn
                         .store_blob(stream::once(future::ok(contents.into())).boxed())
                         .await?;
@@ -145,10 +254,61 @@ fn main(args: Args) -> Result<()> {
                 })
         }
 
-        Subcommand::RunSelfContained { path } => {
+        Subcommand::BackupSqlite { db_dir, connect, dest } => tokio::runtime::Builder::new()
+            .enable_all()
+            .threaded_scheduler()
+            .build()?
+            .block_on(async move {
+                let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
+                conn.backup(
+                    dest,
+                    BackupOptions::default(),
+                    Some(Box::new(|remaining, total| {
+                        log::info!("backup: {} of {} pages remaining", remaining, total);
+                    })),
+                )
+                .await?;
+                Ok(())
+            }),
+
+        Subcommand::ExportSqlite { db_dir, connect, out_file } => tokio::runtime::Builder::new()
+            .enable_all()
+            .threaded_scheduler()
+            .build()?
+            .block_on(async move {
+                let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
+                conn.export_portable(out_file).await?;
+                Ok(())
+            }),
+
+        Subcommand::ImportSqlite { db_dir, in_file } => tokio::runtime::Builder::new()
+            .enable_all()
+            .threaded_scheduler()
+            .build()?
+            .block_on(async move {
+                let _ = SqliteConnection::import_portable(db_dir, in_file).await?;
+                Ok(())
+            }),
+
+        Subcommand::ImportCsv { db_dir, connect, kind, path } => {
+            let rows = load_csv_rows(path, kind.columns())?;
+            tokio::runtime::Builder::new()
+                .enable_all()
+                .threaded_scheduler()
+                .build()?
+                .block_on(async move {
+                    let conn = SqliteConnection::open_with(db_dir, connect.into()).await?;
+                    let report = conn.import_csv(kind, rows).await?;
+                    println!("{} inserted, {} skipped", report.inserted, report.skipped);
+                    Ok(())
+                })
+        }
+
+        Subcommand::RunSelfContained { format, path } => {
             let query = load_query(path)?;
+            let columns = goal_column_names(&query);
             let solns = naive_solve_selfcontained(&query);
-            print_solns(&solns);
+            print_solns(&columns, &solns, format);
             Ok(())
         }
         Subcommand::ValidateQuery { path } => {
@@ -170,6 +330,40 @@ fn load_file(path: Option<PathBuf>) -> Result<Vec<u8>> {
     })
 }
 
+/// Reads `path` (or stdin, if omitted) as a delimited file for `ImportCsv`, splitting each
+/// non-empty line on `,` (or `\t`, if `path` ends in `.tsv`) and checking it has `expected_columns`
+/// fields. Doesn't support quoted fields -- a column containing the delimiter itself isn't
+/// representable.
+fn load_csv_rows(path: Option<PathBuf>, expected_columns: usize) -> Result<Vec<Vec<String>>> {
+    let delimiter = match &path {
+        Some(path) if path.extension().and_then(|ext| ext.to_str()) == Some("tsv") => '\t',
+        _ => ',',
+    };
+    let src = match &path {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut src = String::new();
+            std::io::stdin().read_to_string(&mut src)?;
+            src
+        }
+    };
+    src.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let row: Vec<String> = line.split(delimiter).map(str::to_string).collect();
+            if row.len() != expected_columns {
+                return Err(anyhow::anyhow!(
+                    "expected {} column(s), got {}: {:?}",
+                    expected_columns,
+                    row.len(),
+                    line
+                ));
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
 fn load_query(path: Option<PathBuf>) -> Result<NamelessQuery> {
     let src = match path {
         Some(path) => std::fs::read_to_string(path)?,
@@ -183,7 +377,10 @@ fn load_query(path: Option<PathBuf>) -> Result<NamelessQuery> {
     Ok(query)
 }
 
-async fn repl<C: Connection>(conn: C) -> Result<()> {
+async fn repl<C: Connection>(conn: C) -> Result<()>
+where
+    C::Error: From<tokio::io::Error>,
+{
     // We spawn a thread for stdin, unfortunately.
     let (mut send_wait, mut recv_wait) = mpsc::channel::<()>(1);
     let (mut send_line, mut recv_line) = mpsc::channel::<Result<String>>(1);
@@ -220,6 +417,7 @@ async fn repl<C: Connection>(conn: C) -> Result<()> {
     });
 
     let mut clauses = Vec::new();
+    let mut transaction = None;
     loop {
         send_wait.send(()).await?;
         let line = recv_line.recv().await;
@@ -228,7 +426,7 @@ async fn repl<C: Connection>(conn: C) -> Result<()> {
             None => break,
         };
 
-        match repl_one(line, &mut clauses, &conn).await {
+        match repl_one(line, &mut clauses, &mut transaction, &conn).await {
             Ok(true) => break,
             Ok(false) => {}
             Err(e) => println!("Error: {}", e),
@@ -238,73 +436,247 @@ async fn repl<C: Connection>(conn: C) -> Result<()> {
     Ok(())
 }
 
+/// Handles one line of REPL input. `transaction`, when `Some`, buffers mutating commands into a
+/// `Vec<Mutation>` instead of applying them immediately; `.commit` applies the whole buffer as one
+/// `Connection::batch` call (all-or-nothing), and `.abort` discards it.
+///
+/// `.begin`/`.commit`/`.abort` are recognized directly here rather than added as `Command`
+/// variants, and `.ingest_blob` is matched here ahead of `Command`'s parser: that parser is
+/// generated from a `.lalrpop` grammar that isn't present in this checkout, so `line.parse()`
+/// never produces a `Command` here, `Command::IngestBlob` included.
 async fn repl_one<C: Connection>(
     line: String,
     clauses: &mut Vec<Clause>,
+    transaction: &mut Option<Vec<Mutation>>,
     conn: &C,
-) -> Result<bool> {
-    match line.parse()? {
-        Command::Clause(clause) => {
-            clauses.push(clause);
-        }
-        Command::CreateAtom => {
-            println!("{}", conn.create_atom().await?);
-        }
-        Command::DeleteAtom(atom) => {
-            conn.delete_atom(atom.parse()?).await?;
-        }
-        Command::CreateName(atom, ns, title, upsert) => {
-            conn.create_name(atom.parse()?, &ns, &title, upsert).await?;
-        }
-        Command::DeleteName(ns, title) => {
-            if conn.delete_name(&ns, &title).await? {
-                println!("Deleted name.");
+) -> Result<bool>
+where
+    C::Error: From<tokio::io::Error>,
+{
+    match line.trim() {
+        ".begin" => {
+            if transaction.is_some() {
+                println!("Already in a transaction; run .commit or .abort first.");
             } else {
-                println!("Name did not exist.");
+                *transaction = Some(Vec::new());
             }
+            return Ok(false);
         }
-        Command::CreateEdge(from, to, label) => {
-            if conn.create_edge(from.parse()?, to.parse()?, &label).await? {
-                println!("Edge already existed.");
-            } else {
-                println!("Created edge.");
+        ".commit" => {
+            match transaction.take() {
+                Some(mutations) => {
+                    let n = mutations.len();
+                    let results = conn.batch(mutations).await?;
+                    println!("Committed {} mutation(s):", n);
+                    for result in results {
+                        print_mutation_result(&result);
+                    }
+                }
+                None => println!("Not in a transaction."),
             }
+            return Ok(false);
         }
-        Command::DeleteEdge(from, to, label) => {
-            if conn.delete_edge(from.parse()?, to.parse()?, &label).await? {
-                println!("Deleted edge.");
-            } else {
-                println!("Edge did not exist.");
+        ".abort" => {
+            match transaction.take() {
+                Some(mutations) => println!("Aborted {} queued mutation(s).", mutations.len()),
+                None => println!("Not in a transaction."),
             }
+            return Ok(false);
         }
-        Command::CreateTag(atom, key, value, upsert) => {
-            conn.create_tag(atom.parse()?, &key, &value, upsert).await?;
-        }
-        Command::DeleteTag(atom, key) => {
-            if conn.delete_tag(atom.parse()?, &key).await? {
-                println!("Deleted tag.");
-            } else {
-                println!("Tag did not exist.");
+        _ => {}
+    }
+
+    if let Some(rest) = line.trim().strip_prefix(".ingest_blob ") {
+        let mut parts = rest.trim().splitn(4, char::is_whitespace);
+        let fields = (parts.next(), parts.next(), parts.next(), parts.next());
+        let (atom, kind, mime, path) = match fields {
+            (Some(atom), Some(kind), Some(mime), Some(path)) if !path.is_empty() => {
+                (atom, kind, mime, path)
+            }
+            _ => {
+                println!("Usage: .ingest_blob <ATOM> <KIND> <MIME> <PATH>");
+                return Ok(false);
+            }
+        };
+        let data = g1_common::utils::file_to_stream(path.to_string())
+            .await?
+            .map_err(Into::into)
+            .boxed();
+        let hash = conn.store_blob(data).await?;
+        match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateBlob {
+                    atom: atom.parse()?,
+                    kind: kind.to_string(),
+                    mime: mime.parse()?,
+                    hash,
+                    upsert: false,
+                });
+                println!("Queued. Hash: {}", hash);
+            }
+            None => {
+                conn.create_blob(atom.parse()?, kind, mime.parse()?, hash, false)
+                    .await?;
+                println!("{}", hash);
             }
         }
-        Command::CreateBlob(atom, kind, mime, hash, upsert) => {
-            conn.create_blob(atom.parse()?, &kind, mime.parse()?, hash.parse()?, upsert)
-                .await?;
+        return Ok(false);
+    }
+
+    match line.parse()? {
+        Command::Clause(clause) => {
+            clauses.push(clause);
         }
-        Command::DeleteBlob(atom, kind, mime) => {
-            if conn
-                .delete_blob(atom.parse()?, &kind, mime.parse()?)
-                .await?
-            {
-                println!("Deleted blob.");
-            } else {
-                println!("Blob did not exist.");
+        Command::CreateAtom => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateAtom);
+                println!("Queued.");
             }
-        }
+            None => println!("{}", conn.create_atom().await?),
+        },
+        Command::DeleteAtom(atom) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::DeleteAtom(atom.parse()?));
+                println!("Queued.");
+            }
+            None => conn.delete_atom(atom.parse()?).await?,
+        },
+        Command::CreateName(atom, ns, title, upsert) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateName {
+                    atom: atom.parse()?,
+                    ns,
+                    title,
+                    upsert,
+                });
+                println!("Queued.");
+            }
+            None => {
+                conn.create_name(atom.parse()?, &ns, &title, upsert).await?;
+            }
+        },
+        Command::DeleteName(ns, title) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::DeleteName { ns, title });
+                println!("Queued.");
+            }
+            None => {
+                if conn.delete_name(&ns, &title).await? {
+                    println!("Deleted name.");
+                } else {
+                    println!("Name did not exist.");
+                }
+            }
+        },
+        Command::CreateEdge(from, to, label) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateEdge {
+                    from: from.parse()?,
+                    to: to.parse()?,
+                    label,
+                });
+                println!("Queued.");
+            }
+            None => {
+                if conn.create_edge(from.parse()?, to.parse()?, &label).await? {
+                    println!("Edge already existed.");
+                } else {
+                    println!("Created edge.");
+                }
+            }
+        },
+        Command::DeleteEdge(from, to, label) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::DeleteEdge {
+                    from: from.parse()?,
+                    to: to.parse()?,
+                    label,
+                });
+                println!("Queued.");
+            }
+            None => {
+                if conn.delete_edge(from.parse()?, to.parse()?, &label).await? {
+                    println!("Deleted edge.");
+                } else {
+                    println!("Edge did not exist.");
+                }
+            }
+        },
+        Command::CreateTag(atom, key, value, upsert) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateTag {
+                    atom: atom.parse()?,
+                    key,
+                    value: TagValue::Str(value),
+                    upsert,
+                });
+                println!("Queued.");
+            }
+            None => {
+                conn.create_tag(atom.parse()?, &key, TagValue::Str(value), upsert)
+                    .await?;
+            }
+        },
+        Command::DeleteTag(atom, key) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::DeleteTag {
+                    atom: atom.parse()?,
+                    key,
+                });
+                println!("Queued.");
+            }
+            None => {
+                if conn.delete_tag(atom.parse()?, &key).await? {
+                    println!("Deleted tag.");
+                } else {
+                    println!("Tag did not exist.");
+                }
+            }
+        },
+        Command::CreateBlob(atom, kind, mime, hash, upsert) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::CreateBlob {
+                    atom: atom.parse()?,
+                    kind,
+                    mime: mime.parse()?,
+                    hash: hash.parse()?,
+                    upsert,
+                });
+                println!("Queued.");
+            }
+            None => {
+                conn.create_blob(atom.parse()?, &kind, mime.parse()?, hash.parse()?, upsert)
+                    .await?;
+            }
+        },
+        Command::DeleteBlob(atom, kind, mime) => match transaction.as_mut() {
+            Some(mutations) => {
+                mutations.push(Mutation::DeleteBlob {
+                    atom: atom.parse()?,
+                    kind,
+                    mime: mime.parse()?,
+                });
+                println!("Queued.");
+            }
+            None => {
+                if conn
+                    .delete_blob(atom.parse()?, &kind, mime.parse()?)
+                    .await?
+                {
+                    println!("Deleted blob.");
+                } else {
+                    println!("Blob did not exist.");
+                }
+            }
+        },
         Command::Help => {
             println!(".help    Prints this help message.");
             println!(".quit    Quits the REPL.");
             println!();
+            println!(".begin     Starts buffering mutating commands instead of running them.");
+            println!(".commit    Applies every buffered mutation in one all-or-nothing batch.");
+            println!(".abort     Discards the buffered mutations without applying any of them.");
+            println!();
             println!(".list                  Lists the existing predicates.");
             println!("<CLAUSE>               Adds a clause to a predicate, possibly defining it.");
             println!("?- <QUERY>.            Performs a query.");
@@ -350,6 +722,17 @@ async fn repl_one<C: Connection>(
                 "                                            kind and MIME type from the given"
             );
             println!("                                            atom.");
+            println!();
+            println!(
+                ".ingest_blob <ATOM> <KIND> <MIME> <PATH>    Reads a file from local disk, hashes"
+            );
+            println!(
+                "                                            and stores its content, and creates"
+            );
+            println!(
+                "                                            a blob attached to an atom pointing"
+            );
+            println!("                                            at the computed hash.");
         }
         Command::List => {
             let mut functors = BTreeSet::new();
@@ -366,8 +749,24 @@ async fn repl_one<C: Connection>(
                 clauses: clauses.clone(),
                 goal,
             })?;
+            let columns = goal_column_names(&query);
             let solns = conn.query(None, &query).await?;
-            print_solns(&solns);
+            print_solns(&columns, &solns, OutputFormat::Table);
+        }
+        Command::IngestBlob(atom, kind, mime, path) => {
+            let data = g1_common::utils::file_to_stream(path).await?.map_err(Into::into).boxed();
+            let hash = conn.store_blob(data).await?;
+            match transaction.as_mut() {
+                Some(mutations) => {
+                    let (atom, mime) = (atom.parse()?, mime.parse()?);
+                    mutations.push(Mutation::CreateBlob { atom, kind, mime, hash, upsert: false });
+                    println!("Queued. Hash: {}", hash);
+                }
+                None => {
+                    conn.create_blob(atom.parse()?, &kind, mime.parse()?, hash, false).await?;
+                    println!("{}", hash);
+                }
+            }
         }
         Command::Quit => return Ok(true),
         Command::Undefine(name, argn) => {
@@ -377,19 +776,125 @@ async fn repl_one<C: Connection>(
     Ok(false)
 }
 
-fn print_solns(solns: &[Vec<Arc<str>>]) {
-    println!("Got {} results:", solns.len());
-    for soln in solns {
-        print!("(");
-        let mut first = true;
-        for s in soln {
-            if first {
-                first = false;
-            } else {
-                print!(", ");
+/// Prints one `Connection::batch` result the same way its immediate-mode command would have,
+/// except for `CreateBlob`: its immediate form prints nothing, while here it reports whether the
+/// blob was created or already existed.
+fn print_mutation_result(result: &MutationResult) {
+    match result {
+        MutationResult::CreateAtom(atom) => println!("{}", atom),
+        MutationResult::DeleteAtom => {}
+        MutationResult::CreateName => {}
+        MutationResult::DeleteName(true) => println!("Deleted name."),
+        MutationResult::DeleteName(false) => println!("Name did not exist."),
+        MutationResult::CreateEdge(true) => println!("Edge already existed."),
+        MutationResult::CreateEdge(false) => println!("Created edge."),
+        MutationResult::DeleteEdge(true) => println!("Deleted edge."),
+        MutationResult::DeleteEdge(false) => println!("Edge did not exist."),
+        MutationResult::CreateTag => {}
+        MutationResult::DeleteTag(true) => println!("Deleted tag."),
+        MutationResult::DeleteTag(false) => println!("Tag did not exist."),
+        MutationResult::CreateBlob(true) => println!("Blob already existed."),
+        MutationResult::CreateBlob(false) => println!("Created blob."),
+        MutationResult::DeleteBlob(true) => println!("Deleted blob."),
+        MutationResult::DeleteBlob(false) => println!("Blob did not exist."),
+    }
+}
+
+/// How `print_solns` should render a query's solutions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum OutputFormat {
+    /// A header row of column names, then one parenthesized tuple per solution.
+    Table,
+    /// An array of objects, each keyed by column name.
+    Json,
+    /// A header row of column names, then one comma-separated row per solution. Doesn't quote
+    /// fields -- a value containing a comma isn't representable.
+    Csv,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown format {:?} (expected table, json, or csv)", s)),
+        }
+    }
+}
+
+/// The name of each column `conn.query`'s solutions are in, derived from `query.goal`'s arguments:
+/// a variable's column is named after it, and a literal argument's column is named after the
+/// literal itself (since every solution will repeat that same value in it).
+fn goal_column_names(query: &NamelessQuery) -> Vec<String> {
+    query
+        .goal
+        .args
+        .iter()
+        .map(|arg| match arg {
+            g1_common::nameless::NamelessValue::Var(n) => query
+                .goal_var_names
+                .get(*n as usize)
+                .cloned()
+                .unwrap_or_else(|| n.to_string()),
+            g1_common::nameless::NamelessValue::Str(s) => s.to_string(),
+            g1_common::nameless::NamelessValue::Int(n) => n.to_string(),
+            g1_common::nameless::NamelessValue::Float(n) => n.to_string(),
+            g1_common::nameless::NamelessValue::Bool(b) => b.to_string(),
+            g1_common::nameless::NamelessValue::MetaVar(v) => format!("${}", v),
+        })
+        .collect()
+}
+
+fn print_solns(columns: &[String], solns: &[Vec<Arc<str>>], format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!("Got {} results:", solns.len());
+            if !columns.is_empty() {
+                println!("{}", columns.join(", "));
+            }
+            for soln in solns {
+                print!("(");
+                let mut first = true;
+                for s in soln {
+                    if first {
+                        first = false;
+                    } else {
+                        print!(", ");
+                    }
+                    print!("{:?}", s);
+                }
+                println!(")");
+            }
+        }
+        OutputFormat::Json => {
+            let rows = solns
+                .iter()
+                .map(|soln| {
+                    serde_json::Value::Object(
+                        columns
+                            .iter()
+                            .zip(soln.iter())
+                            .map(|(c, s)| (c.clone(), serde_json::Value::String(s.to_string())))
+                            .collect(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&rows).expect("solutions always serialize")
+            );
+        }
+        OutputFormat::Csv => {
+            println!("{}", columns.join(","));
+            for soln in solns {
+                println!(
+                    "{}",
+                    soln.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(",")
+                );
             }
-            print!("{:?}", s);
         }
-        println!(")");
     }
 }
@@ -0,0 +1,516 @@
+//! A lossless concrete syntax tree (CST) for the G1 query language.
+//!
+//! Unlike the rest of `lang` (whose lalrpop grammar produces a typed AST, and whose `Lexer`
+//! silently discards comments and collapses whitespace), a `SyntaxTree` preserves every byte of
+//! its source: each token remembers the `Trivia` (whitespace and `//` comments) around it, so
+//! `SyntaxTree`'s `Display` reproduces the input byte-for-byte. This is enough to build a
+//! formatter or a linter with fix-its on top of the grammar in this module -- the typed AST can
+//! still be produced from a `SyntaxTree` by a separate lowering pass, not provided here.
+//!
+//! `parse_cst` doesn't reuse the `lexer::Lexer`/lalrpop parser that the rest of this module is
+//! built on: that pipeline reports positions as `Point(line, column)` rather than byte offsets,
+//! which isn't enough to slice the exact trivia between two tokens out of the source, and it
+//! discards comments before a caller ever sees them. So this is a second, independent recursive-
+//! descent scanner/parser over the same token vocabulary, kept intentionally simple (the grammar
+//! has no operator precedence to speak of -- just atoms and predicate application).
+
+use std::fmt::{self, Display, Formatter};
+
+/// What kind of thing a `SyntaxNode` or `SyntaxToken` represents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyntaxKind {
+    /// The root of a `SyntaxTree`, containing every top-level item in source order.
+    Root,
+    /// A `?- predicate.` goal.
+    Query,
+    /// A `predicate.` fact or `predicate :- ...` rule, including its trailing literals.
+    Clause,
+    /// A predicate call, `name(arg, ...)` -- also used, with a leading `!` token, for a negated
+    /// literal in a clause's body.
+    Predicate,
+    /// A single argument to a predicate.
+    Value,
+    /// An `include "path".` directive.
+    IncludeDirective,
+
+    /// `)`
+    ParenClose,
+    /// `(`
+    ParenOpen,
+    /// `_`
+    Underscore,
+    /// `!`
+    Not,
+    /// `,`
+    Comma,
+    /// `.`
+    Period,
+    /// `:-`
+    Turnstile,
+    /// `?-`
+    QueryOp,
+    /// The `include` keyword.
+    Include,
+    /// A string literal.
+    String,
+    /// A variable, either unescaped or enclosed in single-quotes.
+    Var,
+    /// An integer literal.
+    Int,
+    /// A float literal.
+    Float,
+    /// A boolean literal.
+    Bool,
+    /// A byte this scanner didn't recognize as the start of anything else.
+    Error,
+}
+
+/// A run of whitespace or a `//` comment, kept instead of being thrown away like the AST
+/// `Lexer` does, so a `SyntaxTree` can be turned back into exactly the source it came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Trivia {
+    /// A run of whitespace (spaces, tabs, carriage returns, newlines).
+    Whitespace(String),
+    /// A `//` line comment, including the `//` and everything up to (not including) the newline.
+    Comment(String),
+}
+
+impl Display for Trivia {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Trivia::Whitespace(s) | Trivia::Comment(s) => fmt.write_str(s),
+        }
+    }
+}
+
+/// A leaf of a `SyntaxTree`: a single token, together with the `Trivia` immediately before and
+/// after it in the source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntaxToken {
+    /// What kind of token this is.
+    pub kind: SyntaxKind,
+    /// Trivia between the previous token (or the start of the file) and this one.
+    pub leading_trivia: Vec<Trivia>,
+    /// This token's own source text, not including any trivia.
+    pub text: String,
+    /// Trivia between this token and the next one. Always empty except on the last token in a
+    /// `SyntaxTree`, which absorbs whatever trivia follows it to the end of the file.
+    pub trailing_trivia: Vec<Trivia>,
+}
+
+impl Display for SyntaxToken {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for trivia in &self.leading_trivia {
+            Display::fmt(trivia, fmt)?;
+        }
+        fmt.write_str(&self.text)?;
+        for trivia in &self.trailing_trivia {
+            Display::fmt(trivia, fmt)?;
+        }
+        Ok(())
+    }
+}
+
+/// An interior node of a `SyntaxTree`, holding its children -- a mix of nodes and tokens -- in
+/// source order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntaxNode {
+    /// What kind of node this is.
+    pub kind: SyntaxKind,
+    /// This node's children, in source order.
+    pub children: Vec<SyntaxElement>,
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for child in &self.children {
+            Display::fmt(child, fmt)?;
+        }
+        Ok(())
+    }
+}
+
+/// Either a `SyntaxNode` or a `SyntaxToken`, in source order inside a node's `children`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SyntaxElement {
+    /// An interior node.
+    Node(SyntaxNode),
+    /// A leaf token.
+    Token(SyntaxToken),
+}
+
+impl Display for SyntaxElement {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            SyntaxElement::Node(node) => Display::fmt(node, fmt),
+            SyntaxElement::Token(token) => Display::fmt(token, fmt),
+        }
+    }
+}
+
+/// A lossless parse of a source string: a `Root` `SyntaxNode` whose `Display` reproduces the
+/// input byte-for-byte, since every byte of the input ends up as either some token's `text` or
+/// some token's leading or trailing `Trivia`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntaxTree {
+    /// The root node.
+    pub root: SyntaxNode,
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.root, fmt)
+    }
+}
+
+struct RawToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+struct Scanner<'src> {
+    src: &'src [u8],
+    pos: usize,
+}
+
+impl<'src> Scanner<'src> {
+    fn new(src: &'src str) -> Scanner<'src> {
+        Scanner {
+            src: src.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    fn slice_from(&self, start: usize) -> String {
+        String::from_utf8_lossy(&self.src[start..self.pos]).into_owned()
+    }
+
+    /// Consumes a run of whitespace and `//` comments starting at the current position.
+    fn take_trivia(&mut self) -> Vec<Trivia> {
+        let mut out = Vec::new();
+        loop {
+            let start = self.pos;
+            match self.peek() {
+                Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n') => {
+                    while matches!(
+                        self.peek(),
+                        Some(b' ') | Some(b'\t') | Some(b'\r') | Some(b'\n')
+                    ) {
+                        self.pos += 1;
+                    }
+                    out.push(Trivia::Whitespace(self.slice_from(start)));
+                }
+                Some(b'/') if self.peek_at(1) == Some(b'/') => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.pos += 1;
+                    }
+                    out.push(Trivia::Comment(self.slice_from(start)));
+                }
+                _ => break,
+            }
+        }
+        out
+    }
+
+    /// Consumes a `quote`-delimited literal starting at the current position, honoring `\`
+    /// escapes (without validating them -- this scanner only needs to find where the literal
+    /// ends, not what it means).
+    fn take_quoted(&mut self, quote: u8) {
+        self.pos += 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            if b == b'\\' {
+                if self.peek().is_some() {
+                    self.pos += 1;
+                }
+            } else if b == quote {
+                break;
+            }
+        }
+    }
+
+    fn is_ident_start(b: u8) -> bool {
+        b.is_ascii_alphabetic()
+    }
+
+    fn is_ident_continue(b: u8) -> bool {
+        b.is_ascii_alphanumeric() || b == b'_'
+    }
+
+    /// Scans the single token starting at the current position, or `None` at end of input.
+    fn next_token(&mut self) -> Option<RawToken> {
+        let start = self.pos;
+        let b = self.peek()?;
+        let kind = match b {
+            b')' => {
+                self.pos += 1;
+                SyntaxKind::ParenClose
+            }
+            b'(' => {
+                self.pos += 1;
+                SyntaxKind::ParenOpen
+            }
+            b'_' if !Self::is_ident_continue(self.peek_at(1).unwrap_or(b' ')) => {
+                self.pos += 1;
+                SyntaxKind::Underscore
+            }
+            b'.' => {
+                self.pos += 1;
+                SyntaxKind::Period
+            }
+            b',' => {
+                self.pos += 1;
+                SyntaxKind::Comma
+            }
+            b'?' if self.peek_at(1) == Some(b'-') => {
+                self.pos += 2;
+                SyntaxKind::QueryOp
+            }
+            b':' if self.peek_at(1) == Some(b'-') => {
+                self.pos += 2;
+                SyntaxKind::Turnstile
+            }
+            b'!' => {
+                self.pos += 1;
+                SyntaxKind::Not
+            }
+            b'"' => {
+                self.take_quoted(b'"');
+                SyntaxKind::String
+            }
+            b'\'' => {
+                self.take_quoted(b'\'');
+                SyntaxKind::Var
+            }
+            b'-' | b'0'..=b'9' => {
+                self.pos += 1;
+                while matches!(self.peek(), Some(b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                let mut is_float = false;
+                if self.peek() == Some(b'.') && matches!(self.peek_at(1), Some(b'0'..=b'9')) {
+                    is_float = true;
+                    self.pos += 1;
+                    while matches!(self.peek(), Some(b'0'..=b'9')) {
+                        self.pos += 1;
+                    }
+                }
+                if is_float {
+                    SyntaxKind::Float
+                } else {
+                    SyntaxKind::Int
+                }
+            }
+            b if Self::is_ident_start(b) => {
+                while matches!(self.peek(), Some(b) if Self::is_ident_continue(b)) {
+                    self.pos += 1;
+                }
+                match self.slice_from(start).as_str() {
+                    "include" => SyntaxKind::Include,
+                    "true" | "false" => SyntaxKind::Bool,
+                    _ => SyntaxKind::Var,
+                }
+            }
+            _ => {
+                self.pos += 1;
+                SyntaxKind::Error
+            }
+        };
+        Some(RawToken {
+            kind,
+            text: self.slice_from(start),
+        })
+    }
+}
+
+/// A small recursive-descent parser pairing `Scanner`'s raw tokens with the `Trivia` around them.
+struct Parser<'src> {
+    scanner: Scanner<'src>,
+    pending_leading: Vec<Trivia>,
+}
+
+impl<'src> Parser<'src> {
+    fn new(src: &'src str) -> Parser<'src> {
+        let mut scanner = Scanner::new(src);
+        let pending_leading = scanner.take_trivia();
+        Parser {
+            scanner,
+            pending_leading,
+        }
+    }
+
+    /// The kind of the next token, without consuming it.
+    fn peek_kind(&self) -> Option<SyntaxKind> {
+        let mut probe = Scanner {
+            src: self.scanner.src,
+            pos: self.scanner.pos,
+        };
+        probe.next_token().map(|token| token.kind)
+    }
+
+    /// Consumes the next token, attaching the trivia that preceded it.
+    fn bump(&mut self) -> Option<SyntaxToken> {
+        let raw = self.scanner.next_token()?;
+        let leading_trivia = std::mem::take(&mut self.pending_leading);
+        self.pending_leading = self.scanner.take_trivia();
+        Some(SyntaxToken {
+            kind: raw.kind,
+            leading_trivia,
+            text: raw.text,
+            trailing_trivia: Vec::new(),
+        })
+    }
+
+    /// Whatever trivia is left once there are no more tokens -- belongs to the end of the file.
+    fn finish(self) -> Vec<Trivia> {
+        self.pending_leading
+    }
+
+    fn value(&mut self) -> Option<SyntaxNode> {
+        let token = self.bump()?;
+        Some(SyntaxNode {
+            kind: SyntaxKind::Value,
+            children: vec![SyntaxElement::Token(token)],
+        })
+    }
+
+    fn predicate(&mut self) -> Option<SyntaxNode> {
+        let mut children = vec![SyntaxElement::Token(self.bump()?)];
+        if self.peek_kind() == Some(SyntaxKind::ParenOpen) {
+            children.push(SyntaxElement::Token(self.bump()?));
+            while !matches!(self.peek_kind(), None | Some(SyntaxKind::ParenClose)) {
+                children.push(SyntaxElement::Node(self.value()?));
+                if self.peek_kind() == Some(SyntaxKind::Comma) {
+                    children.push(SyntaxElement::Token(self.bump()?));
+                } else {
+                    break;
+                }
+            }
+            if self.peek_kind() == Some(SyntaxKind::ParenClose) {
+                children.push(SyntaxElement::Token(self.bump()?));
+            }
+        }
+        Some(SyntaxNode {
+            kind: SyntaxKind::Predicate,
+            children,
+        })
+    }
+
+    /// A `Clause`'s `?- predicate.` goal, `include "path".` directive, or
+    /// `predicate (:- !?predicate, ...)?.` fact/rule -- whichever the next token starts.
+    fn top_level_item(&mut self) -> Option<SyntaxNode> {
+        match self.peek_kind()? {
+            SyntaxKind::QueryOp => {
+                let mut children = vec![SyntaxElement::Token(self.bump()?)];
+                if let Some(predicate) = self.predicate() {
+                    children.push(SyntaxElement::Node(predicate));
+                }
+                if self.peek_kind() == Some(SyntaxKind::Period) {
+                    children.push(SyntaxElement::Token(self.bump()?));
+                }
+                Some(SyntaxNode {
+                    kind: SyntaxKind::Query,
+                    children,
+                })
+            }
+            SyntaxKind::Include => {
+                let mut children = vec![SyntaxElement::Token(self.bump()?)];
+                if self.peek_kind() == Some(SyntaxKind::String) {
+                    children.push(SyntaxElement::Node(self.value()?));
+                }
+                if self.peek_kind() == Some(SyntaxKind::Period) {
+                    children.push(SyntaxElement::Token(self.bump()?));
+                }
+                Some(SyntaxNode {
+                    kind: SyntaxKind::IncludeDirective,
+                    children,
+                })
+            }
+            _ => {
+                let mut children = vec![SyntaxElement::Node(self.predicate()?)];
+                if self.peek_kind() == Some(SyntaxKind::Turnstile) {
+                    children.push(SyntaxElement::Token(self.bump()?));
+                    loop {
+                        let mut literal_children = Vec::new();
+                        if self.peek_kind() == Some(SyntaxKind::Not) {
+                            literal_children.push(SyntaxElement::Token(self.bump()?));
+                        }
+                        if let Some(predicate) = self.predicate() {
+                            literal_children.push(SyntaxElement::Node(predicate));
+                        }
+                        if literal_children.is_empty() {
+                            break;
+                        }
+                        children.push(SyntaxElement::Node(SyntaxNode {
+                            kind: SyntaxKind::Predicate,
+                            children: literal_children,
+                        }));
+                        if self.peek_kind() == Some(SyntaxKind::Comma) {
+                            children.push(SyntaxElement::Token(self.bump()?));
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                if self.peek_kind() == Some(SyntaxKind::Period) {
+                    children.push(SyntaxElement::Token(self.bump()?));
+                }
+                Some(SyntaxNode {
+                    kind: SyntaxKind::Clause,
+                    children,
+                })
+            }
+        }
+    }
+}
+
+/// Parses `src` into a lossless `SyntaxTree`: a `Root` node containing a `Query`, `Clause`, or
+/// `IncludeDirective` node for each top-level item, in source order.
+///
+/// This never fails -- a byte that isn't the start of anything recognized becomes a `SyntaxKind::
+/// Error` token on its own, and a construct that's missing a piece (e.g. a clause with no
+/// trailing `.`) just ends up with a shorter-than-usual list of children -- so `parse_cst` can be
+/// used on a file being actively edited, not just on one that already parses cleanly. Whatever
+/// `SyntaxTree` comes out always satisfies `tree.to_string() == src`.
+pub fn parse_cst(src: &str) -> SyntaxTree {
+    let mut parser = Parser::new(src);
+    let mut children = Vec::new();
+    while let Some(item) = parser.top_level_item() {
+        children.push(SyntaxElement::Node(item));
+    }
+    let trailing = parser.finish();
+    match last_token_mut(&mut children) {
+        Some(last) => last.trailing_trivia = trailing,
+        None if !trailing.is_empty() => children.push(SyntaxElement::Token(SyntaxToken {
+            kind: SyntaxKind::Error,
+            leading_trivia: trailing,
+            text: String::new(),
+            trailing_trivia: Vec::new(),
+        })),
+        None => {}
+    }
+    SyntaxTree {
+        root: SyntaxNode {
+            kind: SyntaxKind::Root,
+            children,
+        },
+    }
+}
+
+/// The last token in `children`'s rightmost leaf, descending into the last node as needed, so
+/// trivia at the end of the file can be attached where `SyntaxTree::to_string` will actually
+/// render it.
+fn last_token_mut(children: &mut [SyntaxElement]) -> Option<&mut SyntaxToken> {
+    match children.last_mut()? {
+        SyntaxElement::Token(token) => Some(token),
+        SyntaxElement::Node(node) => last_token_mut(&mut node.children),
+    }
+}
@@ -1,5 +1,9 @@
 use derive_more::Display;
 use logos::Logos;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+#[cfg(test)]
+mod lexer_tests;
 
 /// A point.
 #[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
@@ -16,6 +20,88 @@ impl Default for Point {
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Span(pub Point, pub Point);
 
+/// An interned source file, as tracked by a `SourceMap`.
+///
+/// `FileId::default()` is the anonymous "main" source -- the one passed directly to
+/// `Query::from_str`/`parse_query_recovering` rather than pulled in via `include`, so existing
+/// callers that never mention `SourceMap` still get a meaningful (if nameless) id on every
+/// `Clause`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FileId(u32);
+
+/// Interns source file names, borrowing the idea from `proc_macro2`'s source map: every `FileId`
+/// a `Clause` carries traces back to a name here, so a diagnostic that crosses an `include` can
+/// still say which file it came from.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    names: Vec<String>,
+}
+
+impl SourceMap {
+    /// Creates an empty `SourceMap`. Ids are handed out in interning order starting from 0, and
+    /// `FileId::default()` (the "main" source) is id 0 -- so intern the main source's name first if
+    /// it should be resolvable; otherwise `FileId::default()` resolves to the first name interned.
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Interns `name`, returning the next unused `FileId` for it (the first call returns id 0).
+    /// Every call gets its own id, even if the same name is interned more than once, so each
+    /// inclusion's spans stay distinct.
+    pub fn intern(&mut self, name: impl Into<String>) -> FileId {
+        self.names.push(name.into());
+        FileId((self.names.len() - 1) as u32)
+    }
+
+    /// The name a `FileId` was interned with, or `None` if no name was interned under that id
+    /// (e.g. `FileId::default()` on an empty `SourceMap`).
+    pub fn name(&self, id: FileId) -> Option<&str> {
+        self.names.get(id.0 as usize).map(String::as_str)
+    }
+}
+
+/// How serious a `Diagnostic` is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// The input couldn't be parsed at all.
+    Error,
+
+    /// The input parsed, but is probably not what the user meant.
+    Warning,
+}
+
+/// A single problem found while lexing or parsing, with enough position information to point a
+/// user at it (e.g. in an editor or a REPL's error output), rather than aborting with a bare
+/// message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Where the problem is.
+    pub span: Span,
+
+    /// A human-readable description of the problem.
+    pub message: String,
+
+    /// How serious the problem is.
+    pub severity: Severity,
+}
+
+impl Diagnostic {
+    /// Creates an error-severity `Diagnostic`.
+    pub fn error(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            span,
+            message: message.into(),
+            severity: Severity::Error,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}: {}", self.span.0, self.message)
+    }
+}
+
 /// A lexer over strings, producing `Token`s.
 pub struct Lexer<'src> {
     inner: logos::Lexer<Tok, &'src str>,
@@ -57,15 +143,15 @@ impl<'src> Lexer<'src> {
 }
 
 impl<'src> Iterator for Lexer<'src> {
-    type Item = Result<(Point, Token, Point), String>;
+    type Item = Result<(Point, Token, Point), Diagnostic>;
 
-    fn next(&mut self) -> Option<Result<(Point, Token, Point), String>> {
+    fn next(&mut self) -> Option<Result<(Point, Token, Point), Diagnostic>> {
         let out = loop {
             break match self.inner.token {
                 Tok::End => None,
                 Tok::Error => {
-                    let start = self.point(self.inner.range().start);
-                    Some(Err(format!("lexer error at {}", start)))
+                    let message = format!("unrecognized character {:?}", self.inner.slice());
+                    Some(Err(message))
                 }
                 Tok::Comment => {
                     self.inner.advance();
@@ -77,22 +163,36 @@ impl<'src> Iterator for Lexer<'src> {
                 Tok::Period => Some(Ok(Token::Period)),
                 Tok::Comma => Some(Ok(Token::Comma)),
                 Tok::Query => Some(Ok(Token::Query)),
+                Tok::Include => Some(Ok(Token::Include)),
                 Tok::Turnstile => Some(Ok(Token::Turnstile)),
                 Tok::Not => Some(Ok(Token::Not)),
-                Tok::String => {
-                    let s = parse_stringish(self.inner.slice());
-                    Some(Ok(Token::String(s)))
+                Tok::String => match parse_stringish(self.inner.slice()) {
+                    Ok(s) => Some(Ok(Token::String(s))),
+                    Err(message) => Some(Err(message)),
+                },
+                Tok::EscapedVar => match parse_stringish(self.inner.slice()) {
+                    Ok(s) => Some(Ok(Token::Var(s))),
+                    Err(message) => Some(Err(message)),
+                },
+                Tok::True => Some(Ok(Token::Bool(true))),
+                Tok::False => Some(Ok(Token::Bool(false))),
+                Tok::Float => {
+                    let n = self.inner.slice().parse().expect("regex guarantees a valid float");
+                    Some(Ok(Token::Float(n)))
                 }
-                Tok::EscapedVar => {
-                    let s = parse_stringish(self.inner.slice());
-                    Some(Ok(Token::Var(s)))
+                Tok::Int => {
+                    let n = self.inner.slice().parse().expect("regex guarantees a valid int");
+                    Some(Ok(Token::Int(n)))
                 }
                 Tok::Var => Some(Ok(Token::Var(self.inner.slice().to_string()))),
             };
         };
         let (start, end) = self.range();
         self.inner.advance();
-        out.map(|r| r.map(|tok| (start, tok, end)))
+        out.map(|r| match r {
+            Ok(tok) => Ok((start, tok, end)),
+            Err(message) => Err(Diagnostic::error(Span(start, end), message)),
+        })
     }
 }
 
@@ -123,11 +223,23 @@ pub enum Token {
     /// The "query operator", `?-`.
     Query,
 
+    /// The `include` keyword.
+    Include,
+
     /// A string enclosed in double-quotes.
     String(String),
 
     /// A variable, either unescaped or enclosed in single-quotes.
     Var(String),
+
+    /// An integer literal.
+    Int(i64),
+
+    /// A float literal.
+    Float(f64),
+
+    /// A boolean literal (`true` or `false`).
+    Bool(bool),
 }
 
 #[derive(Clone, Copy, Debug, Eq, Logos, PartialEq)]
@@ -159,18 +271,33 @@ enum Tok {
     #[token = "?-"]
     Query,
 
+    #[token = "include"]
+    Include,
+
     #[token = ":-"]
     Turnstile,
 
     #[token = "!"]
     Not,
 
-    #[regex = "\"([^'\"\\\\]|\\\\[trn'\"\\\\])*\""]
+    #[regex = "\"([^'\"\\\\]|\\\\[trn'\"\\\\]|\\\\x[0-9A-Fa-f]{2}|\\\\u\\{[0-9A-Fa-f]{1,6}\\})*\""]
     String,
 
-    #[regex = "'([^'\"\\\\]|\\\\[trn'\"\\\\])*'"]
+    #[regex = "'([^'\"\\\\]|\\\\[trn'\"\\\\]|\\\\x[0-9A-Fa-f]{2}|\\\\u\\{[0-9A-Fa-f]{1,6}\\})*'"]
     EscapedVar,
 
+    #[token = "true"]
+    True,
+
+    #[token = "false"]
+    False,
+
+    #[regex = "-?[0-9]+\\.[0-9]+"]
+    Float,
+
+    #[regex = "-?[0-9]+"]
+    Int,
+
     #[regex = "[A-Za-z][0-9A-Za-z_]*"]
     Var,
 }
@@ -201,11 +328,17 @@ enum StringToken {
     #[token = "\\\\"]
     EscBackslash,
 
+    #[regex = "\\\\x[0-9A-Fa-f]{2}"]
+    EscHex,
+
+    #[regex = "\\\\u\\{[0-9A-Fa-f]{1,6}\\}"]
+    EscUnicode,
+
     #[regex = "[^'\"\\\\]"]
     Char,
 }
 
-fn parse_stringish(s: &str) -> String {
+fn parse_stringish(s: &str) -> Result<String, String> {
     assert!(s.len() >= 2);
     let s = &s[1..s.len() - 1];
 
@@ -222,9 +355,29 @@ fn parse_stringish(s: &str) -> String {
             StringToken::EscSQuote => out.push('\''),
             StringToken::EscDQuote => out.push('"'),
             StringToken::EscBackslash => out.push('\\'),
+            StringToken::EscHex => {
+                let hex = &lexer.slice()[2..];
+                let n = u32::from_str_radix(hex, 16).expect("regex guarantees valid hex digits");
+                if n > 0x7f {
+                    return Err(format!(
+                        "\\x{:02x} is not a valid ASCII scalar value (expected 00-7f)",
+                        n
+                    ));
+                }
+                out.push(char::from_u32(n).expect("n <= 0x7f is always a valid scalar value"));
+            }
+            StringToken::EscUnicode => {
+                let slice = lexer.slice();
+                let hex = &slice[3..slice.len() - 1];
+                let n = u32::from_str_radix(hex, 16).expect("regex guarantees valid hex digits");
+                match char::from_u32(n) {
+                    Some(ch) => out.push(ch),
+                    None => return Err(format!("{:#x} is not a valid Unicode scalar value", n)),
+                }
+            }
             StringToken::Char => out.push_str(lexer.slice()),
         }
         lexer.advance();
     }
-    out
+    Ok(out)
 }
@@ -0,0 +1,34 @@
+//! Behavioral tests for `parse_stringish`'s escape-sequence decoding: the plain escapes
+//! (`\t`/`\r`/`\n`/`\'`/`\"`/`\\`), the two numeric forms (`\xHH`, `\u{HEX}`), and their error
+//! cases -- an out-of-range `\x` byte and a `\u{...}` codepoint that isn't a valid Unicode scalar
+//! value (e.g. a surrogate).
+
+use super::parse_stringish;
+use pretty_assertions::assert_eq;
+
+#[test]
+fn decodes_plain_escapes() {
+    assert_eq!(
+        parse_stringish("\"hello\\tworld\\n\"").unwrap(),
+        "hello\tworld\n"
+    );
+    assert_eq!(parse_stringish("\"a\\'b\\\"c\\\\d\"").unwrap(), "a'b\"c\\d");
+}
+
+#[test]
+fn decodes_hex_and_unicode_escapes() {
+    assert_eq!(parse_stringish("\"\\x41\\x42\"").unwrap(), "AB");
+    assert_eq!(parse_stringish("\"\\u{1f600}\"").unwrap(), "\u{1f600}");
+}
+
+#[test]
+fn rejects_non_ascii_hex_escape() {
+    let err = parse_stringish("\"\\x80\"").unwrap_err();
+    assert_eq!(err, "\\x80 is not a valid ASCII scalar value (expected 00-7f)");
+}
+
+#[test]
+fn rejects_surrogate_unicode_escape() {
+    let err = parse_stringish("\"\\u{d800}\"").unwrap_err();
+    assert_eq!(err, "0xd800 is not a valid Unicode scalar value");
+}
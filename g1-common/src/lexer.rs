@@ -124,6 +124,15 @@ pub enum Token {
     #[regex = "'([^'\"\\\\]|\\\\[trn'\"\\\\])*'"]
     EscapedVar,
 
+    #[token = "true"]
+    True,
+
+    #[token = "false"]
+    False,
+
+    #[regex = "-?[0-9]+\\.[0-9]+"]
+    Float,
+
     #[regex = "[A-Za-z_-][0-9A-Za-z_-]*"]
     Var,
 
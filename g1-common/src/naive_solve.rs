@@ -1,19 +1,46 @@
-//! A naive solver for queries.
+//! A semi-naive solver for queries.
 //!
-//! This should probably not be used for anything except for very small databases and tests. It can
-//! also serve as a reference implementation to compare against for more optimized versions.
+//! Each entry of `query.clauses` is already one predicate's full clause set, and `NamelessQuery`'s
+//! stratification guarantees a predicate's body only ever refers to itself (for self-recursion) or
+//! a strictly earlier entry -- so by the time a predicate's fixpoint runs here, every other
+//! predicate it can reference is already fully and stably computed. Round 0 derives from those
+//! already-stable relations (the predicate's own relation starts empty); every later round re-joins
+//! only the delta of tuples newly derived last round against the stable relations, substituting it
+//! for one self-reference occurrence at a time, instead of re-deriving every tuple from scratch --
+//! so work is proportional to what's actually new rather than quadratic in the relation's size.
 
-use crate::nameless::{NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue};
+use crate::nameless::{
+    NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue, FIRST_IDB_PRED,
+};
 use std::{collections::HashSet, iter::once, sync::Arc};
 
+type Tuple = Vec<Arc<str>>;
+type Relation = HashSet<Tuple>;
+
+/// Renders a non-`Var` `NamelessValue` to the text it'd compare equal to in a relation -- every
+/// base relation (`atoms`/`names`/`edges`/`tags`/`blobs`) is still plain text end to end, so a
+/// typed literal unifies against stored data the same way a bare `Str` always has. Distinguishing,
+/// say, `Int(5)` from `Str("5")` against a stored value that's genuinely typed (a tag's encoded
+/// `TagValue`, say) requires the stored value's own kind to flow down to the solver so it can be
+/// decoded with `Conversion` first -- a larger, separate change to the compiler/solver pipeline
+/// this doesn't include.
+fn literal_text(v: &NamelessValue) -> Arc<str> {
+    match v {
+        NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
+        NamelessValue::Str(s) => s.clone(),
+        NamelessValue::Int(n) => Arc::from(n.to_string()),
+        NamelessValue::Float(n) => Arc::from(n.to_string()),
+        NamelessValue::Bool(b) => Arc::from(b.to_string()),
+        NamelessValue::Var(_) => panic!("literal_text called with a Var"),
+    }
+}
+
 /// Naively solves the given query in a self-contained way (i.e. with all builtin goals failing).
-pub fn naive_solve_selfcontained(query: &NamelessQuery) -> Vec<Vec<Arc<str>>> {
+pub fn naive_solve_selfcontained(query: &NamelessQuery) -> Vec<Tuple> {
     naive_solve(&[], &[], &[], &[], &[], None, query)
 }
 
-/// Naively solves the given query.
-///
-/// TODO: prose
+/// Solves the given query via semi-naive bottom-up evaluation.
 ///
 /// - `atoms`: `atom`
 /// - `names`: `atom, namespace, title`
@@ -28,8 +55,9 @@ pub fn naive_solve(
     blobs: &[(Arc<str>, Arc<str>, Arc<str>, Arc<str>)],
     limit: Option<usize>,
     query: &NamelessQuery,
-) -> Vec<Vec<Arc<str>>> {
-    let mut tuples = vec![HashSet::new(); query.clauses.len() + 5];
+) -> Vec<Tuple> {
+    let mut tuples: Vec<Relation> =
+        vec![HashSet::new(); query.clauses.len() + FIRST_IDB_PRED as usize];
 
     // Add all the builtin tuples.
     tuples[0].extend(atoms.iter().map(|atom| vec![atom.clone()]));
@@ -51,26 +79,28 @@ pub fn naive_solve(
         vec![atom.clone(), kind.clone(), mime.clone(), hash.clone()]
     }));
 
-    // For each predicate, compute its tuples.
-    for (pred_idx, pred) in query.clauses.iter().enumerate() {
-        // Repeatedly compute new tuples until no new tuples are added. This is needed to handle
-        // recursion.
-        loop {
-            let mut new_tuples = HashSet::new();
-            for clause in pred {
-                new_tuples.extend(compute_new_tuples(&tuples, clause));
-            }
+    // For each predicate, compute its tuples via semi-naive fixpoint iteration.
+    for (pred_idx, clauses) in query.clauses.iter().enumerate() {
+        let pred_id = pred_idx as u32 + FIRST_IDB_PRED;
 
-            // Remove the tuples already computed.
-            new_tuples.retain(|x| !tuples[pred_idx + 5].contains(x));
+        let mut delta: Relation = clauses
+            .iter()
+            .flat_map(|clause| eval_clause_round0(&tuples, clause))
+            .collect();
+        delta.retain(|t| !tuples[pred_id as usize].contains(t));
+        tuples[pred_id as usize].extend(delta.iter().cloned());
 
-            // If no new tuples were computed, we can stop.
-            if new_tuples.is_empty() {
+        while !delta.is_empty() {
+            let mut new_delta: Relation = clauses
+                .iter()
+                .flat_map(|clause| eval_clause_delta(&tuples, clause, pred_id, &delta))
+                .collect();
+            new_delta.retain(|t| !tuples[pred_id as usize].contains(t));
+            if new_delta.is_empty() {
                 break;
             }
-
-            // Otherwise, add the new tuples in.
-            tuples[pred_idx + 5].extend(new_tuples);
+            tuples[pred_id as usize].extend(new_delta.iter().cloned());
+            delta = new_delta;
         }
     }
 
@@ -84,8 +114,6 @@ pub fn naive_solve(
                 .iter()
                 .zip(&query.goal.args)
                 .all(|(val, arg)| match arg {
-                    NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
-                    NamelessValue::Str(s) => s == val,
                     NamelessValue::Var(n) => match &vars[*n as usize] {
                         Some(s) => s == &val,
                         None => {
@@ -93,6 +121,7 @@ pub fn naive_solve(
                             true
                         }
                     },
+                    lit => &literal_text(lit) == val,
                 })
         });
     if let Some(limit) = limit {
@@ -102,62 +131,149 @@ pub fn naive_solve(
     }
 }
 
-fn compute_new_tuples(
-    tuples: &Vec<HashSet<Vec<Arc<str>>>>,
-    clause: &NamelessClause,
-) -> HashSet<Vec<Arc<str>>> {
-    assert!(clause.body_neg.is_empty(), "TODO negation");
-
-    make_envs(tuples, &clause.body_pos, clause.vars)
-        .map(|env| {
-            clause
-                .head
-                .iter()
-                .map(|x| match x {
-                    NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
-                    NamelessValue::Str(s) => s,
-                    NamelessValue::Var(n) => env[*n as usize].as_ref().unwrap(),
-                })
-                .cloned()
-                .collect()
-        })
+/// Evaluates `clause`'s body entirely against the currently-stable `tuples` -- round 0 of a
+/// predicate's fixpoint, run before that predicate has derived any tuples of its own yet.
+fn eval_clause_round0(tuples: &[Relation], clause: &NamelessClause) -> Relation {
+    make_envs(&|_, name| &tuples[name as usize], &clause.body_pos, clause.vars)
+        .filter(|env| neg_holds(tuples, &clause.body_neg, env))
+        .filter(|env| filters_hold(&clause.body_filters, env))
+        .map(|env| project_head(&clause.head, &env))
         .collect()
 }
 
+/// Evaluates `clause`'s body for one semi-naive round: for each occurrence of `pred_id` (the
+/// predicate whose fixpoint is running) in `clause.body_pos`, substitutes `delta` for that
+/// occurrence and the stable full relation for every other atom (other self-references included),
+/// unioning the results over every occurrence. A clause with no occurrence of `pred_id` can't
+/// produce anything new past round 0, since every other predicate it reads is already stable.
+fn eval_clause_delta(
+    tuples: &[Relation],
+    clause: &NamelessClause,
+    pred_id: u32,
+    delta: &Relation,
+) -> Relation {
+    let mut out = Relation::new();
+    for (i, pred) in clause.body_pos.iter().enumerate() {
+        if pred.name != pred_id {
+            continue;
+        }
+        out.extend(
+            make_envs(
+                &|idx, name| if idx == i { delta } else { &tuples[name as usize] },
+                &clause.body_pos,
+                clause.vars,
+            )
+            .filter(|env| neg_holds(tuples, &clause.body_neg, env))
+            .filter(|env| filters_hold(&clause.body_filters, env))
+            .map(|env| project_head(&clause.head, &env)),
+        );
+    }
+    out
+}
+
+/// Every way to bind `body`'s variables (`0..vars`) by joining the relations `relation_for` yields
+/// for each body atom -- `relation_for(i, name)` is the relation `body[i]` (a reference to
+/// predicate `name`) should be read from, letting callers substitute a delta relation for one
+/// specific occurrence while the rest still read the full, stable relation.
 fn make_envs<'a>(
-    tuples: &'a Vec<HashSet<Vec<Arc<str>>>>,
+    relation_for: &'a dyn Fn(usize, u32) -> &'a Relation,
     body: &'a [NamelessPredicate],
     vars: u32,
+) -> Box<dyn Iterator<Item = Vec<Option<Arc<str>>>> + 'a> {
+    make_envs_from(relation_for, body, vars, 0)
+}
+
+fn make_envs_from<'a>(
+    relation_for: &'a dyn Fn(usize, u32) -> &'a Relation,
+    body: &'a [NamelessPredicate],
+    vars: u32,
+    offset: usize,
 ) -> Box<dyn Iterator<Item = Vec<Option<Arc<str>>>> + 'a> {
     if body.is_empty() {
         Box::new(once((0..vars).map(|_| None).collect::<Vec<_>>()))
     } else {
         let pred = &body[0];
-        Box::new(make_envs(tuples, &body[1..], vars).flat_map(move |env| {
-            tuples[pred.name as usize].iter().filter_map(move |tuple| {
-                let mut env = env.clone();
-                for (arg, val) in pred.args.iter().zip(tuple) {
-                    match arg {
-                        NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
-                        NamelessValue::Str(s) => {
-                            if s != val {
-                                return None;
+        let rel = relation_for(offset, pred.name);
+        Box::new(
+            make_envs_from(relation_for, &body[1..], vars, offset + 1).flat_map(move |env| {
+                rel.iter().filter_map(move |tuple| {
+                    let mut env = env.clone();
+                    for (arg, val) in pred.args.iter().zip(tuple) {
+                        match arg {
+                            NamelessValue::Var(n) => {
+                                let slot = &mut env[*n as usize];
+                                if let Some(s) = slot {
+                                    if s != val {
+                                        return None;
+                                    }
+                                } else {
+                                    *slot = Some(val.clone());
+                                }
                             }
-                        }
-                        NamelessValue::Var(n) => {
-                            let slot = &mut env[*n as usize];
-                            if let Some(s) = slot {
-                                if s != val {
+                            lit => {
+                                if &literal_text(lit) != val {
                                     return None;
                                 }
-                            } else {
-                                *slot = Some(val.clone());
                             }
                         }
                     }
-                }
-                Some(env)
-            })
-        }))
+                    Some(env)
+                })
+            }),
+        )
     }
 }
+
+/// Whether every `body_neg` predicate is *absent* for the values `env` assigns -- i.e. whether
+/// negation succeeds. `NamelessClause::validate` guarantees every variable a negated predicate's
+/// args use is already bound by `body_pos`, so each negated predicate resolves to one concrete
+/// tuple whose (non-)membership in the already-stable lower-stratum relation decides it.
+fn neg_holds(tuples: &[Relation], neg: &[NamelessPredicate], env: &[Option<Arc<str>>]) -> bool {
+    neg.iter().all(|pred| {
+        let tuple: Tuple = pred
+            .args
+            .iter()
+            .map(|arg| match arg {
+                NamelessValue::Var(n) => env[*n as usize]
+                    .clone()
+                    .expect("negated-predicate variable not bound by a positive body predicate"),
+                lit => literal_text(lit),
+            })
+            .collect();
+        !tuples[pred.name as usize].contains(&tuple)
+    })
+}
+
+/// Whether every comparison-builtin call in `filters` holds for the values `env` assigns --
+/// `eq`/`lt`/`le` (predicate numbers `5`-`7`) compare their two args lexicographically, with the
+/// result flipped if the call was negated (`!lt(x, y)` is `x >= y`, and so on).
+fn filters_hold(filters: &[(bool, NamelessPredicate)], env: &[Option<Arc<str>>]) -> bool {
+    filters.iter().all(|(negated, pred)| {
+        let resolve = |arg: &NamelessValue| -> Arc<str> {
+            match arg {
+                NamelessValue::Var(n) => env[*n as usize]
+                    .clone()
+                    .expect("comparison-builtin variable not bound by a positive body predicate"),
+                lit => literal_text(lit),
+            }
+        };
+        let a = resolve(&pred.args[0]);
+        let b = resolve(&pred.args[1]);
+        let holds = match pred.name {
+            5 => a == b,
+            6 => a < b,
+            7 => a <= b,
+            n => panic!("unknown comparison builtin: {}", n),
+        };
+        holds != *negated
+    })
+}
+
+fn project_head(head: &[NamelessValue], env: &[Option<Arc<str>>]) -> Tuple {
+    head.iter()
+        .map(|x| match x {
+            NamelessValue::Var(n) => env[*n as usize].clone().unwrap(),
+            lit => literal_text(lit),
+        })
+        .collect()
+}
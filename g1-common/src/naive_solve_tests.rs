@@ -0,0 +1,90 @@
+//! Behavioral tests for `naive_solve`'s semi-naive fixpoint and negation, built directly from
+//! `NamelessQuery`/`NamelessClause` rather than through `Query::parse_str` -- the legacy grammar
+//! that backs `FromStr for Query` has no `.lalrpop` source in this checkout (see the `mod parser`
+//! comment in `lib.rs`), so these are hand-assembled instead.
+
+use crate::nameless::{NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue};
+use crate::naive_solve::naive_solve;
+use pretty_assertions::assert_eq;
+use std::sync::Arc;
+
+fn str(s: &str) -> Arc<str> {
+    Arc::from(s)
+}
+
+fn lit(s: &str) -> NamelessValue {
+    NamelessValue::Str(str(s))
+}
+
+/// `reachable(X, Y)` is the transitive closure of the builtin `edge/3` relation (ignoring its
+/// label, pinned here to `"e"`); `indirect(X, Y)` is `reachable` minus whatever's a direct edge.
+/// Solving `?- indirect("a", X)` over the chain `a -> b -> c -> d` should find `c` and `d` but not
+/// `b`, exercising both multi-round fixpoint derivation (round 0 only reaches `b`) and negation
+/// against a lower stratum.
+#[test]
+fn transitive_closure_excludes_direct_edges() {
+    let reachable_base = NamelessClause {
+        vars: 2,
+        head: vec![NamelessValue::Var(0), NamelessValue::Var(1)],
+        body_pos: vec![NamelessPredicate {
+            name: 2,
+            args: vec![NamelessValue::Var(0), NamelessValue::Var(1), lit("e")],
+        }],
+        body_neg: Vec::new(),
+        body_filters: Vec::new(),
+    };
+    let reachable_step = NamelessClause {
+        vars: 3,
+        head: vec![NamelessValue::Var(0), NamelessValue::Var(2)],
+        body_pos: vec![
+            NamelessPredicate {
+                name: 8,
+                args: vec![NamelessValue::Var(0), NamelessValue::Var(1)],
+            },
+            NamelessPredicate {
+                name: 2,
+                args: vec![NamelessValue::Var(1), NamelessValue::Var(2), lit("e")],
+            },
+        ],
+        body_neg: Vec::new(),
+        body_filters: Vec::new(),
+    };
+    let indirect = NamelessClause {
+        vars: 2,
+        head: vec![NamelessValue::Var(0), NamelessValue::Var(1)],
+        body_pos: vec![NamelessPredicate {
+            name: 8,
+            args: vec![NamelessValue::Var(0), NamelessValue::Var(1)],
+        }],
+        body_neg: vec![NamelessPredicate {
+            name: 2,
+            args: vec![NamelessValue::Var(0), NamelessValue::Var(1), lit("e")],
+        }],
+        body_filters: Vec::new(),
+    };
+
+    let query = NamelessQuery {
+        clauses: vec![vec![reachable_base, reachable_step], vec![indirect]],
+        goal_vars: 1,
+        goal_var_names: vec!["X".to_string()],
+        goal: NamelessPredicate {
+            name: 9,
+            args: vec![lit("a"), NamelessValue::Var(0)],
+        },
+    };
+
+    let edges = [
+        (str("a"), str("b"), str("e")),
+        (str("b"), str("c"), str("e")),
+        (str("c"), str("d"), str("e")),
+    ];
+
+    let mut results = naive_solve(&[], &[], &edges, &[], &[], None, &query);
+    results.sort();
+    // Each result tuple is the full matched `indirect` row, not just the free `X` column -- the
+    // bound "a" column comes along for the ride.
+    assert_eq!(
+        results,
+        vec![vec![str("a"), str("c")], vec![str("a"), str("d")]]
+    );
+}
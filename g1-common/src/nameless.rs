@@ -14,7 +14,7 @@ use serde_derive::{Deserialize, Serialize};
 use std::{collections::HashMap, convert::TryFrom, sync::Arc};
 
 /// A nameless representation of values.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, PartialOrd, Serialize)]
 pub enum NamelessValue {
     /// A metavariable.
     MetaVar(String),
@@ -24,6 +24,15 @@ pub enum NamelessValue {
 
     /// A variable.
     Var(u32),
+
+    /// An integer literal.
+    Int(i64),
+
+    /// A float literal.
+    Float(f64),
+
+    /// A boolean literal.
+    Bool(bool),
 }
 
 impl Value {
@@ -43,6 +52,9 @@ impl Value {
             }
             Value::MetaVar(v) => Ok(NamelessValue::MetaVar(v)),
             Value::Str(s) => Ok(NamelessValue::Str(strings.store_owned(s))),
+            Value::Int(n) => Ok(NamelessValue::Int(n)),
+            Value::Float(n) => Ok(NamelessValue::Float(n)),
+            Value::Bool(b) => Ok(NamelessValue::Bool(b)),
             Value::Var(v) => {
                 let n = var_env
                     .iter()
@@ -81,11 +93,33 @@ impl From<Arc<str>> for NamelessValue {
     }
 }
 
+/// The data-backed builtin predicates (`atom/1`, `name/3`, `edge/3`, `tag/3`, `blob/4`), occupying
+/// predicate numbers `0`-`4`; each is enumerable as a base relation.
+const BUILTINS: &[(&str, usize)] = &[
+    ("atom", 1),
+    ("name", 3),
+    ("edge", 3),
+    ("tag", 3),
+    ("blob", 4),
+];
+
+/// The comparison builtins (predicate numbers `5`-`7`): `eq/2`, `lt/2`, and `le/2`. Unlike the data
+/// builtins, these don't enumerate facts -- they filter bindings `body_pos` already established, so
+/// (per `NamelessClause::body_filters`) a clause may call them with both args already bound without
+/// either variable needing to appear in a positive data position. Negating one (`!lt(x, y)`, say)
+/// gets you the complementary comparison (`x >= y`) for free.
+const COMPARE_BUILTINS: &[(&str, usize)] = &[("eq", 2), ("lt", 2), ("le", 2)];
+
+/// The first predicate number available to user-defined (IDB) predicates, after the data builtins
+/// (`0`-`4`) and the comparison builtins (`5`-`7`).
+pub const FIRST_IDB_PRED: u32 = 8;
+
 /// A nameless representation of predicates.
 #[derive(Clone, Debug)]
 pub struct NamelessPredicate {
     /// The name of the predicate. Note that the names `0`-`4` refer to the builtin predicates
-    /// `atom/1`, `name/3`, `edge/3`, `tag/3`, and `blob/4`, respectively.
+    /// `atom/1`, `name/3`, `edge/3`, `tag/3`, and `blob/4`, and `5`-`7` refer to the comparison
+    /// builtins `eq/2`, `lt/2`, and `le/2`, respectively.
     pub name: u32,
 
     /// The arguments to the predicate.
@@ -133,6 +167,12 @@ pub struct NamelessClause {
 
     /// The positive predicates in the body of the clause.
     pub body_neg: Vec<NamelessPredicate>,
+
+    /// Comparison-builtin calls in the body (`eq`/`lt`/`le`, predicate numbers `5`-`7`), paired
+    /// with whether they were negated. These only filter bindings `body_pos`/`body_neg` already
+    /// established -- they never bind a variable themselves, which is why their args are never
+    /// counted toward a variable's "appears in a positive position" requirement.
+    pub body_filters: Vec<(bool, NamelessPredicate)>,
 }
 
 impl NamelessClause {
@@ -149,12 +189,18 @@ impl NamelessClause {
             .collect::<Result<_, _>>()?;
         let mut body_pos = Vec::new();
         let mut body_neg = Vec::new();
+        let mut body_filters = Vec::new();
         for (n, p) in body {
-            let p = p.to_nameless(strings, pred_env, &mut var_env, !n)?;
-            if n {
-                body_neg.push(p);
+            if COMPARE_BUILTINS.contains(&(p.name.as_str(), p.args.len())) {
+                let p = p.to_nameless(strings, pred_env, &mut var_env, false)?;
+                body_filters.push((n, p));
             } else {
-                body_pos.push(p);
+                let p = p.to_nameless(strings, pred_env, &mut var_env, !n)?;
+                if n {
+                    body_neg.push(p);
+                } else {
+                    body_pos.push(p);
+                }
             }
         }
 
@@ -178,6 +224,7 @@ impl NamelessClause {
             head,
             body_pos,
             body_neg,
+            body_filters,
         })
     }
 }
@@ -191,6 +238,11 @@ pub struct NamelessQuery {
     /// The number of variables used in the predicate to solve for.
     pub goal_vars: u32,
 
+    /// The name the user wrote for each variable in `goal`, indexed the same way `goal`'s
+    /// `NamelessValue::Var` indices are (so `goal_var_names[n]` is variable `n`'s name). A hole
+    /// (`_`) is recorded as `"_"`, same as `Value::Hole`'s `var_env` entry.
+    pub goal_var_names: Vec<String>,
+
     /// The predicate to solve for.
     pub goal: NamelessPredicate,
 }
@@ -208,14 +260,6 @@ impl NamelessQuery {
 
     /// Tries to convert a `Query` to a `NamelessQuery`.
     pub fn from_query<E: Error>(q: Query) -> Result<NamelessQuery, E> {
-        const BUILTINS: &[(&str, usize)] = &[
-            ("atom", 1),
-            ("name", 3),
-            ("edge", 3),
-            ("tag", 3),
-            ("blob", 4),
-        ];
-
         // Group the clauses by their functor.
         let mut all_clauses = HashMap::<_, Vec<_>>::new();
         for clause in q.clauses {
@@ -234,7 +278,10 @@ impl NamelessQuery {
             for (_, body) in clauses {
                 for (_, pred) in body {
                     let callee_functor: (&str, _) = (&pred.name, pred.args.len());
-                    if callee_functor != caller_functor && !BUILTINS.contains(&callee_functor) {
+                    if callee_functor != caller_functor
+                        && !BUILTINS.contains(&callee_functor)
+                        && !COMPARE_BUILTINS.contains(&callee_functor)
+                    {
                         toposort.add_dependency(callee_functor, caller_functor);
                     }
                 }
@@ -253,10 +300,11 @@ impl NamelessQuery {
         // Create the original predicate environment.
         let mut pred_env = BUILTINS
             .iter()
+            .chain(COMPARE_BUILTINS)
             .enumerate()
             .map(|(i, (name, argn))| ((name.to_string(), *argn), i as u32))
             .collect::<HashMap<_, _>>();
-        let mut pred_env_counter = 5;
+        let mut pred_env_counter = FIRST_IDB_PRED;
 
         // Convert the clauses, filling in the predicate environment.
         let mut strings = StringPool::default();
@@ -299,11 +347,13 @@ impl NamelessQuery {
             .to_nameless(&mut strings, &pred_env, &mut var_env, false)?;
         let goal_vars = u32::try_from(var_env.len())
             .map_err(|_| Error::invalid_query("too many variables used".to_string()))?;
+        let goal_var_names = var_env.into_iter().map(|(name, _)| name).collect();
 
         // Return.
         Ok(NamelessQuery {
             clauses,
             goal_vars,
+            goal_var_names,
             goal,
         })
     }
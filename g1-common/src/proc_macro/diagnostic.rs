@@ -0,0 +1,140 @@
+//! Diagnostics for the `query!` macro, kept independent of `proc_macro::Diagnostic` (a nightly-only
+//! type) so this crate can stay on stable. `g1-macros` is the crate that actually owns a real
+//! `proc_macro::Diagnostic` (see its `emit_diagnostics` for the nightly/stable split).
+
+use crate::{
+    proc_macro::token::{Span, Token},
+    validated::{visitors::BUILTINS, ValidationError},
+};
+use lalrpop_util::ParseError;
+use std::convert::Infallible;
+
+/// How serious a `Diagnostic` is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Level {
+    /// The query can't be used as written.
+    Error,
+
+    /// A suggestion for how to fix the diagnostic it's attached to.
+    Help,
+
+    /// Supplementary context for the diagnostic it's attached to.
+    Note,
+}
+
+/// A single diagnostic pointing at a span in the user's source, with zero or more attached
+/// `Help`/`Note` children (e.g. a `Help` listing the predicates currently in scope).
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    /// How serious this diagnostic is.
+    pub level: Level,
+
+    /// Where in the user's source this diagnostic points.
+    pub span: Span,
+
+    /// The diagnostic's message.
+    pub message: String,
+
+    /// Supplementary diagnostics attached to this one.
+    pub children: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    /// Creates a bare `Level::Error` diagnostic with no children.
+    fn error(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            span,
+            message: message.into(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Attaches a child diagnostic.
+    fn with_child(mut self, child: Diagnostic) -> Diagnostic {
+        self.children.push(child);
+        self
+    }
+}
+
+/// Builds a `Help` diagnostic listing every builtin and user-defined predicate in scope, to attach
+/// to a "no such clause" error.
+fn predicates_in_scope(span: Span, defined: &[(String, usize)]) -> Diagnostic {
+    let mut message = String::from("predicates in scope:");
+    for (name, argn) in BUILTINS.iter() {
+        message.push_str(&format!("\n  {}/{}", name, argn));
+    }
+    for (name, argn) in defined {
+        message.push_str(&format!("\n  {}/{}", name, argn));
+    }
+    Diagnostic {
+        level: Level::Help,
+        span,
+        message,
+        children: Vec::new(),
+    }
+}
+
+/// Converts a single `ValidationError<Span>` into a `Diagnostic`. `defined` is the set of
+/// user-defined predicates (name, arity), gathered from the frontend IR before it was consumed by
+/// `to_validated`; it's only used to populate the "predicates in scope" help for "no such clause"
+/// errors.
+pub(crate) fn from_validation_error(
+    err: ValidationError<Span>,
+    defined: &[(String, usize)],
+) -> Diagnostic {
+    let message = err.to_string();
+    match err {
+        ValidationError::NoSuchClauseBuilding { span, .. } => {
+            Diagnostic::error(span, message).with_child(predicates_in_scope(span, defined))
+        }
+        ValidationError::NoSuchClause { span, .. } => {
+            Diagnostic::error(span, message).with_child(predicates_in_scope(span, defined))
+        }
+        ValidationError::Stratification {
+            negated_span,
+            head_span,
+            ..
+        } => Diagnostic::error(negated_span, message).with_child(Diagnostic {
+            level: Level::Note,
+            span: head_span,
+            message: "the clause whose stratum this illegally depends on".to_owned(),
+            children: Vec::new(),
+        }),
+        ValidationError::BadArgn { span, .. }
+        | ValidationError::UnboundVariable { span, .. }
+        | ValidationError::VariableOutOfRange { span, .. } => Diagnostic::error(span, message),
+        // `GoalVisitor::finish` is the only place this is raised, and it has no span of its own to
+        // point at; fall back to the call site of the macro as a whole.
+        ValidationError::IllegalRecursion => Diagnostic::error(Span::default(), message),
+    }
+}
+
+/// Converts a `lalrpop_util::ParseError` (as produced by `ir::Query::parse`) into a `Diagnostic`.
+pub(crate) fn from_parse_error(err: ParseError<Span, Token, Infallible>) -> Diagnostic {
+    match err {
+        ParseError::InvalidToken { location } => Diagnostic::error(location, "invalid token"),
+        ParseError::UnrecognizedEOF { location, expected } => Diagnostic::error(
+            location,
+            format!(
+                "unexpected end of input, expected one of: {}",
+                expected.join(", ")
+            ),
+        ),
+        ParseError::UnrecognizedToken {
+            token: (start, token, _),
+            expected,
+        } => Diagnostic::error(
+            start,
+            format!(
+                "unexpected {:?}, expected one of: {}",
+                token,
+                expected.join(", ")
+            ),
+        ),
+        ParseError::ExtraToken {
+            token: (start, token, _),
+        } => Diagnostic::error(start, format!("unexpected extra token {:?}", token)),
+        ParseError::User { error } => match error {},
+    }
+}
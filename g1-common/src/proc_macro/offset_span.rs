@@ -0,0 +1,37 @@
+//! A line/column span anchored to a specific source string, for rendering caret-underlined
+//! diagnostics outside of a real proc-macro invocation (e.g. in a REPL or editor).
+
+use crate::proc_macro::token::Span;
+use proc_macro2::LineColumn;
+use std::fmt::{Formatter, Result as FmtResult};
+
+/// A span expressed as start/end line/column pairs, produced by mapping a `proc_macro::token::Span`
+/// down with `ValidatedQuery::map_span` (or `ValidationError::map_span`).
+///
+/// Unlike `proc_macro::token::Span`, this doesn't depend on being inside an actual proc-macro
+/// invocation to be useful -- `fmt_span` prints a `line:column` prefix that works the same whether
+/// the query came from the `query!` macro or a runtime-parsed string.
+#[derive(Clone, Copy, Debug)]
+pub struct OffsetSpan {
+    /// The first line/column covered by the span.
+    pub start: LineColumn,
+
+    /// The line/column just past the end of the span.
+    pub end: LineColumn,
+}
+
+impl crate::validated::Span for OffsetSpan {
+    fn fmt_span(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "{}:{}: ", self.start.line, self.start.column)
+    }
+}
+
+impl From<Span> for OffsetSpan {
+    fn from(span: Span) -> OffsetSpan {
+        let inner: proc_macro2::Span = span.into();
+        OffsetSpan {
+            start: inner.start(),
+            end: inner.end(),
+        }
+    }
+}
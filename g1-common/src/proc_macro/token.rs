@@ -2,7 +2,7 @@
 
 use proc_macro2::{Delimiter, Ident, Literal, TokenStream, TokenTree};
 use quote::quote;
-use syn::LitStr;
+use syn::{LitByteStr, LitChar, LitFloat, LitInt, LitStr};
 
 /// A wrapper around `proc_macro2::Span`, to give a `Default` impl.
 #[derive(Clone, Copy, Debug)]
@@ -23,6 +23,19 @@ impl Span {
             .map(Span::from)
             .unwrap_or_else(Span::default)
     }
+
+    /// Folds an iterator of `Span`s into the single `Span` covering all of them, for grammar
+    /// reductions that join more than two children at once -- e.g. a predicate's span is its name
+    /// token joined with every argument's span and its closing paren. Returns `Span::default()`
+    /// for an empty iterator.
+    pub fn join_all(spans: impl IntoIterator<Item = Span>) -> Span {
+        spans
+            .into_iter()
+            .fold(None, |acc: Option<Span>, span| {
+                Some(acc.map_or(span, |acc| acc.join(span)))
+            })
+            .unwrap_or_default()
+    }
 }
 
 impl Default for Span {
@@ -57,6 +70,10 @@ impl crate::validated::Span for Span {
 /// A `Token` flattened from a `TokenStream`.
 #[derive(Clone, Debug)]
 pub enum Token {
+    /// A boolean literal -- the identifiers `true`/`false`, recognized here rather than left as
+    /// plain `Ident`s.
+    Bool(bool, Span),
+
     /// A close curly brace character (`}`).
     BraceClose(Span),
 
@@ -69,6 +86,15 @@ pub enum Token {
     /// An open square bracket character (`[`).
     BracketOpen(Span),
 
+    /// A byte-string literal (`b"hello"`).
+    ByteString(LitByteStr),
+
+    /// A character literal (`'a'`).
+    Char(LitChar),
+
+    /// A floating-point literal (`2.3`).
+    Float(LitFloat),
+
     /// A hole. This is technically an identifier, but LALRPOP needs it in order to match against
     /// it.
     Hole(Span),
@@ -76,8 +102,10 @@ pub enum Token {
     /// An identifier.
     Ident(Ident),
 
-    /// A literal character (`'a'`), number (`2.3`), etc. Notably does not include a literal string
-    /// (`"hello"`).
+    /// An integer literal (`42`, `42i32`), including its optional suffix.
+    Int(LitInt),
+
+    /// A literal this lexer doesn't give special treatment to (e.g. a byte literal like `b'a'`).
     Literal(Literal),
 
     /// A literal string (`"hello"`).
@@ -97,7 +125,8 @@ impl Token {
     /// Returns the span of the token.
     pub fn span(&self) -> Span {
         match self {
-            Token::BraceClose(span)
+            Token::Bool(_, span)
+            | Token::BraceClose(span)
             | Token::BraceOpen(span)
             | Token::BracketClose(span)
             | Token::BracketOpen(span)
@@ -105,7 +134,11 @@ impl Token {
             | Token::ParenClose(span)
             | Token::ParenOpen(span)
             | Token::Punct(_, span) => *span,
+            Token::ByteString(lit) => lit.span().into(),
+            Token::Char(lit) => lit.span().into(),
+            Token::Float(lit) => lit.span().into(),
             Token::Ident(ident) => ident.span().into(),
+            Token::Int(lit) => lit.span().into(),
             Token::Literal(literal) => literal.span().into(),
             Token::LiteralString(lit_str) => lit_str.span().into(),
         }
@@ -113,11 +146,44 @@ impl Token {
 }
 
 fn append_tokenstream(tokens: &mut Vec<Token>, stream: TokenStream) {
-    for tree in stream {
+    let mut trees = stream.into_iter().peekable();
+    while let Some(tree) = trees.next() {
+        if let TokenTree::Punct(ref punct) = tree {
+            if punct.as_char() == '-' {
+                if let Some(TokenTree::Literal(_)) = trees.peek() {
+                    let minus = punct.clone();
+                    let literal = match trees.next() {
+                        Some(TokenTree::Literal(literal)) => literal,
+                        _ => unreachable!("just peeked a Literal"),
+                    };
+                    append_negated_literal(tokens, minus, literal);
+                    continue;
+                }
+            }
+        }
         append_tokentree(tokens, tree);
     }
 }
 
+/// Appends the token for `-literal`, given the `-` and the literal that is the very next token
+/// after it. A `TokenStream` doesn't record whitespace before a literal, so `- 5` is treated the
+/// same as `-5`. Falls back to emitting the `-` and the literal separately if the
+/// literal isn't a number (e.g. `-"hello"`, which isn't meaningful but isn't this function's place
+/// to reject).
+fn append_negated_literal(tokens: &mut Vec<Token>, minus: proc_macro2::Punct, literal: Literal) {
+    let span = Span::from(minus.span()).join(literal.span().into());
+    if let Ok(lit) = syn::parse2::<LitInt>(quote! { #literal }) {
+        let repr = format!("-{}{}", lit.base10_digits(), lit.suffix());
+        tokens.push(Token::Int(LitInt::new(&repr, span.into())));
+    } else if let Ok(lit) = syn::parse2::<LitFloat>(quote! { #literal }) {
+        let repr = format!("-{}{}", lit.base10_digits(), lit.suffix());
+        tokens.push(Token::Float(LitFloat::new(&repr, span.into())));
+    } else {
+        tokens.push(Token::Punct('-', minus.span().into()));
+        append_tokentree(tokens, TokenTree::Literal(literal));
+    }
+}
+
 fn append_tokentree(tokens: &mut Vec<Token>, tree: TokenTree) {
     match tree {
         TokenTree::Group(group) => {
@@ -137,10 +203,26 @@ fn append_tokentree(tokens: &mut Vec<Token>, tree: TokenTree) {
                 Delimiter::None => {}
             }
         }
-        TokenTree::Ident(ident) => tokens.push(Token::Ident(ident)),
+        TokenTree::Ident(ident) => {
+            if ident == "true" {
+                tokens.push(Token::Bool(true, ident.span().into()))
+            } else if ident == "false" {
+                tokens.push(Token::Bool(false, ident.span().into()))
+            } else {
+                tokens.push(Token::Ident(ident))
+            }
+        }
         TokenTree::Literal(literal) => {
-            if let Ok(lit_str) = syn::parse2(quote! { #literal }) {
-                tokens.push(Token::LiteralString(lit_str))
+            if let Ok(lit) = syn::parse2(quote! { #literal }) {
+                tokens.push(Token::LiteralString(lit))
+            } else if let Ok(lit) = syn::parse2(quote! { #literal }) {
+                tokens.push(Token::Int(lit))
+            } else if let Ok(lit) = syn::parse2(quote! { #literal }) {
+                tokens.push(Token::Float(lit))
+            } else if let Ok(lit) = syn::parse2(quote! { #literal }) {
+                tokens.push(Token::Char(lit))
+            } else if let Ok(lit) = syn::parse2(quote! { #literal }) {
+                tokens.push(Token::ByteString(lit))
             } else {
                 tokens.push(Token::Literal(literal))
             }
@@ -12,12 +12,15 @@ use crate::{
 impl Value {
     fn build_on<'a, T: ValueVisitor<'a, Span>>(&'a self, visitor: &mut T) {
         match self {
+            Value::Bool(b, span) => visitor.visit_arg_bool(*b, *span),
+            Value::Float(n, lit) => visitor.visit_arg_float(*n, lit.span().into()),
             Value::Hole(span) => visitor.visit_arg_hole(*span),
             Value::Ident(s, lit) => {
                 let mut span = Span::from(lit.span());
                 span.is_ident = true;
                 visitor.visit_arg_string(s, span)
             }
+            Value::Int(n, lit) => visitor.visit_arg_int(*n, lit.span().into()),
             Value::String(s, lit) => visitor.visit_arg_string(s, lit.span().into()),
             Value::Var(s, ident) => visitor.visit_arg_var(s, ident.span().into()),
         }
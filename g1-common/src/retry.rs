@@ -0,0 +1,206 @@
+//! An opt-in retry layer over any `Connection`, for transient failures (a momentarily locked
+//! database, a dropped connection) that would likely succeed on a second attempt.
+
+use crate::{
+    nameless::NamelessQuery, Atom, Bytes, ChangeEntry, ChangeFilter, Connection, Error, Hash,
+    Mime, Mutation, MutationResult, TagValue,
+};
+use futures::prelude::*;
+use rand::Rng;
+use std::{pin::Pin, sync::Arc, time::Duration};
+use tokio::time::delay_for;
+
+#[cfg(test)]
+mod retry_tests;
+
+/// Tuning for `RetryingConnection`'s backoff between attempts at a transient failure.
+///
+/// Each retry waits `base_delay * 2.pow(attempt)`, capped at `max_delay`, plus up to 50% jitter --
+/// so concurrent callers that all hit the same lock don't all wake up and retry in lockstep.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryConfig {
+    /// How many times to retry a transient failure before giving up and returning it. `0` means
+    /// transient failures are never retried, only classified.
+    pub max_retries: u32,
+
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+
+    /// The most any single retry will wait, regardless of how high `base_delay * 2.pow(attempt)`
+    /// would otherwise climb -- this is what keeps a genuinely dead database from making a caller
+    /// hang forever instead of eventually surfacing the error.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            max_retries: 5,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The delay to wait before the given retry attempt (`0` for the first retry), before jitter.
+    fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        match self.base_delay.checked_mul(1 << attempt.min(31)) {
+            Some(delay) if delay < self.max_delay => delay,
+            _ => self.max_delay,
+        }
+    }
+}
+
+/// Wraps a `Connection`, retrying any operation that fails with a transient error (per
+/// `Error::is_transient`) with exponential backoff and jitter, up to `RetryConfig::max_retries`
+/// attempts. A permanent error is returned immediately, with no retry.
+///
+/// `store_blob` is passed through unwrapped: its input is a one-shot stream, already consumed by
+/// the time a failure could be observed, so there's nothing left to retry with.
+#[derive(Clone, Debug)]
+pub struct RetryingConnection<C> {
+    conn: C,
+    config: RetryConfig,
+}
+
+impl<C: Connection> RetryingConnection<C> {
+    /// Wraps `conn` so its operations retry transient failures per `config`.
+    pub fn new(conn: C, config: RetryConfig) -> RetryingConnection<C> {
+        RetryingConnection { conn, config }
+    }
+
+    /// Runs `op`, retrying it with backoff as long as it keeps failing with a transient error and
+    /// retries remain, per `self.config`.
+    async fn retry<F, Fut, T>(&self, mut op: F) -> Result<T, C::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, C::Error>>,
+    {
+        for attempt in 0.. {
+            match op().await {
+                Ok(val) => return Ok(val),
+                Err(err) if attempt < self.config.max_retries && err.is_transient() => {
+                    let delay = self.config.delay_for_attempt(attempt);
+                    let jitter = rand::thread_rng().gen_range(0.0, 0.5);
+                    delay_for(delay.mul_f64(1.0 + jitter)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("0.. never ends")
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: Connection> Connection for RetryingConnection<C> {
+    type Error = C::Error;
+
+    async fn create_atom(&self) -> Result<Atom, Self::Error> {
+        self.retry(|| self.conn.create_atom()).await
+    }
+
+    async fn delete_atom(&self, atom: Atom) -> Result<(), Self::Error> {
+        self.retry(|| self.conn.delete_atom(atom)).await
+    }
+
+    async fn create_name(
+        &self,
+        atom: Atom,
+        ns: &str,
+        title: &str,
+        upsert: bool,
+    ) -> Result<(), Self::Error> {
+        self.retry(|| self.conn.create_name(atom, ns, title, upsert))
+            .await
+    }
+
+    async fn delete_name(&self, ns: &str, title: &str) -> Result<bool, Self::Error> {
+        self.retry(|| self.conn.delete_name(ns, title)).await
+    }
+
+    async fn create_edge(&self, from: Atom, to: Atom, label: &str) -> Result<bool, Self::Error> {
+        self.retry(|| self.conn.create_edge(from, to, label)).await
+    }
+
+    async fn delete_edge(&self, from: Atom, to: Atom, label: &str) -> Result<bool, Self::Error> {
+        self.retry(|| self.conn.delete_edge(from, to, label)).await
+    }
+
+    async fn create_tag(
+        &self,
+        atom: Atom,
+        key: &str,
+        value: TagValue,
+        upsert: bool,
+    ) -> Result<(), Self::Error> {
+        self.retry(|| self.conn.create_tag(atom, key, value.clone(), upsert))
+            .await
+    }
+
+    async fn delete_tag(&self, atom: Atom, key: &str) -> Result<bool, Self::Error> {
+        self.retry(|| self.conn.delete_tag(atom, key)).await
+    }
+
+    async fn create_blob(
+        &self,
+        atom: Atom,
+        kind: &str,
+        mime: Mime,
+        hash: Hash,
+        upsert: bool,
+    ) -> Result<(), Self::Error> {
+        self.retry(|| self.conn.create_blob(atom, kind, mime.clone(), hash, upsert))
+            .await
+    }
+
+    async fn delete_blob(&self, atom: Atom, kind: &str, mime: Mime) -> Result<bool, Self::Error> {
+        self.retry(|| self.conn.delete_blob(atom, kind, mime.clone()))
+            .await
+    }
+
+    async fn fetch_blob(
+        &self,
+        hash: Hash,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, Self::Error>> + Send>>, Self::Error> {
+        self.retry(|| self.conn.fetch_blob(hash)).await
+    }
+
+    async fn store_blob(
+        &self,
+        data: Pin<Box<dyn Stream<Item = Result<Bytes, Self::Error>> + Send + 'static>>,
+    ) -> Result<Hash, Self::Error> {
+        // Not retried -- see the type's doc comment.
+        self.conn.store_blob(data).await
+    }
+
+    async fn query(
+        &self,
+        limit: Option<usize>,
+        query: &NamelessQuery,
+    ) -> Result<Vec<Vec<Arc<str>>>, Self::Error> {
+        self.retry(|| self.conn.query(limit, query)).await
+    }
+
+    async fn query_stream(
+        &self,
+        limit: Option<usize>,
+        query: &NamelessQuery,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Vec<Arc<str>>, Self::Error>> + Send>>, Self::Error>
+    {
+        // Like `fetch_blob`, retried: a fresh, not-yet-consumed stream is produced each attempt, so
+        // there's no partial-consumption hazard the way there is for `store_blob`.
+        self.retry(|| self.conn.query_stream(limit, query)).await
+    }
+
+    async fn batch(&self, mutations: Vec<Mutation>) -> Result<Vec<MutationResult>, Self::Error> {
+        self.retry(|| self.conn.batch(mutations.clone())).await
+    }
+
+    async fn subscribe(
+        &self,
+        filter: ChangeFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = ChangeEntry> + Send>>, Self::Error> {
+        self.retry(|| self.conn.subscribe(filter.clone())).await
+    }
+}
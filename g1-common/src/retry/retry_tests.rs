@@ -0,0 +1,41 @@
+//! Behavioral tests for `RetryConfig::delay_for_attempt`'s exponential backoff and cap -- the
+//! computation `RetryingConnection::retry` actually sleeps on, separated out here since it's pure
+//! and synchronous (no `tokio` runtime, no jitter) and so directly assertable.
+
+use super::RetryConfig;
+use pretty_assertions::assert_eq;
+use std::time::Duration;
+
+fn config() -> RetryConfig {
+    RetryConfig {
+        max_retries: 5,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(2),
+    }
+}
+
+#[test]
+fn doubles_each_attempt_until_the_cap() {
+    let config = config();
+    assert_eq!(config.delay_for_attempt(0), Duration::from_millis(10));
+    assert_eq!(config.delay_for_attempt(1), Duration::from_millis(20));
+    assert_eq!(config.delay_for_attempt(3), Duration::from_millis(80));
+    assert_eq!(config.delay_for_attempt(7), Duration::from_millis(1280));
+}
+
+#[test]
+fn caps_at_max_delay_once_doubling_would_exceed_it() {
+    let config = config();
+    // 10ms * 2^8 = 2560ms, past the 2s cap.
+    assert_eq!(config.delay_for_attempt(8), Duration::from_secs(2));
+    assert_eq!(config.delay_for_attempt(20), Duration::from_secs(2));
+}
+
+#[test]
+fn clamps_the_shift_so_huge_attempts_dont_panic_or_overflow() {
+    let config = config();
+    assert_eq!(
+        config.delay_for_attempt(31),
+        config.delay_for_attempt(1_000_000)
+    );
+}
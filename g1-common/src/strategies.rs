@@ -13,16 +13,20 @@ const STRING_REGEX: &'static str = "[ -~]*";
 impl Arbitrary for Value {
     type Parameters = ();
     type Strategy = LazyTupleUnion<(
-        WA<Map<<i64 as Arbitrary>::Strategy, fn(i64) -> Value>>,
         WA<Map<&'static str, fn(String) -> Value>>,
         WA<Map<&'static str, fn(String) -> Value>>,
+        WA<Map<<i64 as Arbitrary>::Strategy, fn(i64) -> Value>>,
+        WA<Map<<f64 as Arbitrary>::Strategy, fn(f64) -> Value>>,
+        WA<Map<<bool as Arbitrary>::Strategy, fn(bool) -> Value>>,
     )>;
 
     fn arbitrary_with((): ()) -> Self::Strategy {
         prop_oneof![
-            any::<i64>().prop_map(Value::Int),
-            STRING_REGEX.prop_map(Value::String),
+            STRING_REGEX.prop_map(Value::Str),
             STRING_REGEX.prop_map(Value::Var),
+            any::<i64>().prop_map(Value::Int),
+            any::<f64>().prop_map(Value::Float),
+            any::<bool>().prop_map(Value::Bool),
         ]
     }
 }
@@ -70,6 +74,6 @@ impl Arbitrary for Query {
 
     fn arbitrary_with((): ()) -> Self::Strategy {
         (vec(any::<Clause>(), 0..10), any::<Predicate>())
-            .prop_map(|(clauses, predicate)| Query { clauses, predicate })
+            .prop_map(|(clauses, goal)| Query { clauses, goal })
     }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    nameless::{NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue},
+    nameless::{NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue, FIRST_IDB_PRED},
     Error,
 };
 use std::convert::TryFrom;
@@ -11,7 +11,7 @@ impl NamelessQuery {
             let i = u32::try_from(i)
                 .map_err(|_| E::invalid_query("too many predicates".to_string()))?;
             for clause in clauses {
-                clause.validate(i + 5)?;
+                clause.validate(i + FIRST_IDB_PRED)?;
             }
         }
         Ok(())
@@ -33,6 +33,12 @@ impl NamelessClause {
             let max_pred = pred_num - 1;
             pred.validate(max_pred, true, &mut positivities)?;
         }
+        // Comparison builtins only ever filter already-established bindings, never bind a
+        // variable themselves -- so, regardless of whether the user wrote `!`, their args are
+        // validated as if negated.
+        for (_, pred) in &self.body_filters {
+            pred.validate(pred_num, true, &mut positivities)?;
+        }
 
         for (i, positive) in positivities.into_iter().enumerate() {
             if !positive {
@@ -1,4 +1,6 @@
-use crate::validated::{Span, ValidatedClause, ValidatedPredicate, ValidatedQuery, ValidatedValue};
+use crate::validated::{
+    Span, ValidatedClause, ValidatedPredicate, ValidatedQuery, ValidatedValue, ValidationError,
+};
 
 impl<S: Span> ValidatedValue<S> {
     /// Changes the `Span` type inside the `ValidatedValue`.
@@ -48,3 +50,57 @@ impl<S: Span> ValidatedQuery<S> {
         }
     }
 }
+
+impl<S: Span> ValidationError<S> {
+    /// Changes the `Span` type inside the `ValidationError`, e.g. to carry the proc-macro spans
+    /// used by `query!` over to the byte-offset spans a runtime parser (like a REPL) would rather
+    /// render a snippet against.
+    pub fn map_span<F: FnMut(S) -> S2, S2: Span>(self, f: &mut F) -> ValidationError<S2> {
+        match self {
+            ValidationError::BadArgn {
+                expected,
+                found,
+                span,
+            } => ValidationError::BadArgn {
+                expected,
+                found,
+                span: f(span),
+            },
+            ValidationError::IllegalRecursion => ValidationError::IllegalRecursion,
+            ValidationError::NoSuchClause { argn, name, span } => ValidationError::NoSuchClause {
+                argn,
+                name,
+                span: f(span),
+            },
+            ValidationError::NoSuchClauseBuilding { argn, name, span } => {
+                ValidationError::NoSuchClauseBuilding {
+                    argn,
+                    name,
+                    span: f(span),
+                }
+            }
+            ValidationError::Stratification {
+                negated,
+                negated_span,
+                head_span,
+            } => ValidationError::Stratification {
+                negated,
+                negated_span: f(negated_span),
+                head_span: f(head_span),
+            },
+            ValidationError::UnboundVariable { span, var } => ValidationError::UnboundVariable {
+                span: f(span),
+                var,
+            },
+            ValidationError::VariableOutOfRange {
+                max_vars,
+                span,
+                var,
+            } => ValidationError::VariableOutOfRange {
+                max_vars,
+                span: f(span),
+                var,
+            },
+        }
+    }
+}
@@ -2,6 +2,7 @@
 
 mod map_span;
 pub(crate) mod pool;
+mod solve;
 mod validate;
 pub mod visitors;
 
@@ -25,13 +26,22 @@ impl Span for () {
 }
 
 /// The kind of a value.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum ValidatedValueInner {
     /// A string.
     Str(Arc<str>),
 
     /// A variable.
     Var(u32),
+
+    /// An integer literal.
+    Int(i64),
+
+    /// A float literal.
+    Float(f64),
+
+    /// A boolean literal.
+    Bool(bool),
 }
 
 impl Display for ValidatedValueInner {
@@ -39,12 +49,15 @@ impl Display for ValidatedValueInner {
         match self {
             ValidatedValueInner::Str(s) => write!(fmt, "{:?}", s),
             ValidatedValueInner::Var(n) => write!(fmt, "#{}", n),
+            ValidatedValueInner::Int(n) => write!(fmt, "{}", n),
+            ValidatedValueInner::Float(n) => write!(fmt, "{}", n),
+            ValidatedValueInner::Bool(b) => write!(fmt, "{}", b),
         }
     }
 }
 
 /// A data value.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ValidatedValue<S: Span> {
     /// The data.
     pub inner: ValidatedValueInner,
@@ -60,7 +73,7 @@ impl<S: Span> Display for ValidatedValue<S> {
 }
 
 /// A call to a rule.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ValidatedPredicate<S: Span> {
     /// The name of the predicate.
     pub name: i32,
@@ -90,7 +103,7 @@ impl<S: Span> Display for ValidatedPredicate<S> {
 }
 
 /// A single clause, used for deduction.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ValidatedClause<S: Span> {
     /// The head of the clause.
     pub head: ValidatedPredicate<S>,
@@ -134,7 +147,7 @@ impl<S: Span> Display for ValidatedClause<S> {
 }
 
 /// A complete query to the database.
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ValidatedQuery<S: Span> {
     /// The clauses to be used by the query.
     pub clauses: Vec<ValidatedClause<S>>,
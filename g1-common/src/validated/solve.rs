@@ -0,0 +1,150 @@
+//! A naive, self-contained solver for `ValidatedQuery`s.
+//!
+//! This mirrors `crate::naive_solve`'s self-contained mode (every builtin predicate simply fails)
+//! but works directly on `ValidatedQuery` instead of `NamelessQuery`, so callers that only have a
+//! `ValidatedQuery` -- such as `g1-repl` -- don't need to round-trip through the older nameless
+//! representation just to try a query out.
+
+use crate::validated::{
+    Span, ValidatedClause, ValidatedPredicate, ValidatedQuery, ValidatedValueInner,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+/// Renders a non-`Var` `ValidatedValueInner` to the text it'd compare equal to in a relation, for
+/// the same reason `naive_solve::literal_text` exists -- every fact here is still plain `Arc<str>`
+/// text, so a typed literal unifies against it the same way a bare `Str` always has.
+fn literal_text(v: &ValidatedValueInner) -> Arc<str> {
+    match v {
+        ValidatedValueInner::Str(s) => s.clone(),
+        ValidatedValueInner::Int(n) => Arc::from(n.to_string()),
+        ValidatedValueInner::Float(n) => Arc::from(n.to_string()),
+        ValidatedValueInner::Bool(b) => Arc::from(b.to_string()),
+        ValidatedValueInner::Var(_) => panic!("literal_text called with a Var"),
+    }
+}
+
+impl<S: Span> ValidatedQuery<S> {
+    /// Naively solves the query with no backing facts, so every builtin predicate (`atom`, `name`,
+    /// `edge`, `tag`, `blob`) fails.
+    pub fn solve_selfcontained(&self) -> Vec<Vec<Arc<str>>> {
+        // `self.clauses` is already grouped into contiguous runs by `head.name` (see
+        // `visitors::GoalVisitor::finish`), so a single pass is enough to split them back apart.
+        let mut by_name: Vec<(i32, Vec<&ValidatedClause<S>>)> = Vec::new();
+        for clause in &self.clauses {
+            match by_name.last_mut() {
+                Some((name, clauses)) if *name == clause.head.name => clauses.push(clause),
+                _ => by_name.push((clause.head.name, vec![clause])),
+            }
+        }
+
+        let mut tuples = HashMap::<i32, HashSet<Vec<Arc<str>>>>::new();
+        for (name, clauses) in &by_name {
+            loop {
+                let mut new_tuples = HashSet::new();
+                for clause in clauses {
+                    new_tuples.extend(compute_new_tuples(&tuples, clause));
+                }
+
+                let existing = tuples.entry(*name).or_insert_with(HashSet::new);
+                let before = existing.len();
+                existing.extend(new_tuples);
+                if existing.len() == before {
+                    break;
+                }
+            }
+        }
+
+        let empty = HashSet::new();
+        tuples
+            .get(&self.goal.name)
+            .unwrap_or(&empty)
+            .iter()
+            .filter(|tuple| matches_goal(&self.goal, self.goal_vars, tuple))
+            .cloned()
+            .collect()
+    }
+}
+
+fn matches_goal<S: Span>(goal: &ValidatedPredicate<S>, goal_vars: u32, tuple: &[Arc<str>]) -> bool {
+    let mut vars = vec![None; goal_vars as usize];
+    tuple
+        .iter()
+        .zip(&goal.args)
+        .all(|(val, arg)| match &arg.inner {
+            ValidatedValueInner::Var(n) => match &vars[*n as usize] {
+                Some(bound) => bound == val,
+                None => {
+                    vars[*n as usize] = Some(val.clone());
+                    true
+                }
+            },
+            lit => &literal_text(lit) == val,
+        })
+}
+
+fn compute_new_tuples<S: Span>(
+    tuples: &HashMap<i32, HashSet<Vec<Arc<str>>>>,
+    clause: &ValidatedClause<S>,
+) -> HashSet<Vec<Arc<str>>> {
+    assert!(
+        clause.body.iter().all(|(negated, _)| !negated),
+        "TODO negation"
+    );
+
+    make_envs(tuples, &clause.body, clause.vars)
+        .map(|env| {
+            clause
+                .head
+                .args
+                .iter()
+                .map(|arg| match &arg.inner {
+                    ValidatedValueInner::Var(n) => env[*n as usize]
+                        .clone()
+                        .expect("validated clause left a head variable unbound"),
+                    lit => literal_text(lit),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn make_envs<'a, S: Span>(
+    tuples: &'a HashMap<i32, HashSet<Vec<Arc<str>>>>,
+    body: &'a [(bool, ValidatedPredicate<S>)],
+    vars: u32,
+) -> Box<dyn Iterator<Item = Vec<Option<Arc<str>>>> + 'a> {
+    if body.is_empty() {
+        return Box::new(std::iter::once(
+            (0..vars).map(|_| None).collect::<Vec<_>>(),
+        ));
+    }
+
+    let (_, pred) = &body[0];
+    let empty = HashSet::new();
+    let facts = tuples.get(&pred.name).unwrap_or(&empty);
+    Box::new(make_envs(tuples, &body[1..], vars).flat_map(move |env| {
+        facts.iter().filter_map(move |tuple| {
+            let mut env = env.clone();
+            for (arg, val) in pred.args.iter().zip(tuple) {
+                match &arg.inner {
+                    ValidatedValueInner::Var(n) => {
+                        let slot = &mut env[*n as usize];
+                        match slot {
+                            Some(bound) if bound != val => return None,
+                            _ => *slot = Some(val.clone()),
+                        }
+                    }
+                    lit => {
+                        if &literal_text(lit) != val {
+                            return None;
+                        }
+                    }
+                }
+            }
+            Some(env)
+        })
+    }))
+}
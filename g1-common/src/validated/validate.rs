@@ -22,42 +22,9 @@ pub enum ValidationError<S: Span> {
         span: S,
     },
 
-    /// Ill-formed recursion was detected.
-    BadRecursion {
-        /// The head of the clause doing the calling.
-        caller: ValidatedPredicate<S>,
-
-        /// The predicate being called.
-        callee: ValidatedPredicate<S>,
-
-        /// Whether the clause was being called negatively.
-        negated: bool,
-    },
-
-    /// A variable with a bad index was found.
-    BadVariable {
-        /// The number of variables declared in the clause or goal.
-        max_vars: u32,
-
-        /// The span of the variable.
-        span: S,
-
-        /// The invalid index.
-        var: u32,
-    },
-
     /// Ill-formed recursion was detected while building the `ValidationQuery`.
     IllegalRecursion,
 
-    /// A variable was never used in a positive position.
-    NeverUsedPositively {
-        /// The clause in which the variable is not used.
-        clause: ValidatedClause<S>,
-
-        /// The variable.
-        var: u32,
-    },
-
     /// No clause with the given functor existed.
     NoSuchClause {
         /// The number of arguments.
@@ -81,6 +48,41 @@ pub enum ValidationError<S: Span> {
         /// The span of the call.
         span: S,
     },
+
+    /// A body predicate reference crossed a stratum boundary: it (or, if negated, a predicate in
+    /// its own stratum) refers to a predicate that hasn't been fully computed by the time the
+    /// referencing clause's stratum runs.
+    Stratification {
+        /// Whether the offending reference was negated.
+        negated: bool,
+
+        /// The span of the body-predicate reference that illegally crosses the stratum boundary.
+        negated_span: S,
+
+        /// The span of the clause head whose stratum it illegally depends on.
+        head_span: S,
+    },
+
+    /// A variable was never used in a positive position, so it's never actually bound to a value.
+    UnboundVariable {
+        /// The span of the clause in which the variable is unbound.
+        span: S,
+
+        /// The variable.
+        var: u32,
+    },
+
+    /// A variable with an out-of-range index was found.
+    VariableOutOfRange {
+        /// The number of variables declared in the clause or goal.
+        max_vars: u32,
+
+        /// The span of the variable.
+        span: S,
+
+        /// The invalid index.
+        var: u32,
+    },
 }
 
 impl<S: Span> Display for ValidationError<S> {
@@ -99,25 +101,48 @@ impl<S: Span> Display for ValidationError<S> {
                 )
             }
 
-            ValidationError::BadRecursion {
-                caller,
-                callee,
+            ValidationError::IllegalRecursion => {
+                // TODO: Better diagnostic...
+                write!(fmt, "invalid recursion detected")
+            }
+
+            ValidationError::NoSuchClause { argn, name, span } => {
+                span.fmt_span(fmt)?;
+                write!(fmt, "no such clause {}/{}", name, argn)
+            }
+
+            ValidationError::NoSuchClauseBuilding { argn, name, span } => {
+                span.fmt_span(fmt)?;
+                write!(fmt, "no such clause {}/{}", name, argn)
+            }
+
+            ValidationError::Stratification {
                 negated: false,
+                negated_span,
+                ..
             } => {
-                caller.span.fmt_span(fmt)?;
-                write!(fmt, "{} cannot call {}", caller, callee)
+                negated_span.fmt_span(fmt)?;
+                write!(fmt, "this reference flows into a not-yet-computed stratum")
             }
 
-            ValidationError::BadRecursion {
-                caller,
-                callee,
+            ValidationError::Stratification {
                 negated: true,
+                negated_span,
+                ..
             } => {
-                caller.span.fmt_span(fmt)?;
-                write!(fmt, "{} cannot call !{}", caller, callee)
+                negated_span.fmt_span(fmt)?;
+                write!(
+                    fmt,
+                    "this negated reference flows into a not-yet-computed stratum"
+                )
+            }
+
+            ValidationError::UnboundVariable { span, var } => {
+                span.fmt_span(fmt)?;
+                write!(fmt, "variable #{} was never used positively", var)
             }
 
-            ValidationError::BadVariable {
+            ValidationError::VariableOutOfRange {
                 max_vars,
                 span,
                 var,
@@ -129,26 +154,6 @@ impl<S: Span> Display for ValidationError<S> {
                     var, max_vars
                 )
             }
-
-            ValidationError::IllegalRecursion => {
-                // TODO: Better diagnostic...
-                write!(fmt, "invalid recursion detected")
-            }
-
-            ValidationError::NeverUsedPositively { clause, var } => {
-                clause.span.fmt_span(fmt)?;
-                write!(fmt, "variable #{} was never used positively", var)
-            }
-
-            ValidationError::NoSuchClause { argn, name, span } => {
-                span.fmt_span(fmt)?;
-                write!(fmt, "no such clause {}/{}", name, argn)
-            }
-
-            ValidationError::NoSuchClauseBuilding { argn, name, span } => {
-                span.fmt_span(fmt)?;
-                write!(fmt, "no such clause {}/{}", name, argn)
-            }
         }
     }
 }
@@ -170,18 +175,18 @@ impl<S: Span> ValidatedQuery<S> {
                 let j = pred.name;
                 if negated {
                     if j >= i {
-                        return Err(ValidationError::BadRecursion {
-                            caller: clause.head.clone(),
-                            callee: pred.clone(),
+                        return Err(ValidationError::Stratification {
                             negated,
+                            negated_span: pred.span.clone(),
+                            head_span: clause.head.span.clone(),
                         });
                     }
                 } else {
                     if j > i {
-                        return Err(ValidationError::BadRecursion {
-                            caller: clause.head.clone(),
-                            callee: pred.clone(),
+                        return Err(ValidationError::Stratification {
                             negated,
+                            negated_span: pred.span.clone(),
+                            head_span: clause.head.span.clone(),
                         });
                     }
                 }
@@ -242,7 +247,7 @@ impl<S: Span> ValidatedQuery<S> {
             if var < self.goal_vars {
                 Ok(())
             } else {
-                Err(ValidationError::BadVariable {
+                Err(ValidationError::VariableOutOfRange {
                     max_vars: self.goal_vars,
                     span: span.clone(),
                     var,
@@ -250,6 +255,92 @@ impl<S: Span> ValidatedQuery<S> {
             }
         })
     }
+
+    /// Like `validate`, but doesn't stop at the first problem found: every check below runs
+    /// independently of whether the earlier ones passed, so this can return more than one error.
+    /// The `query!` macro uses this to report every problem with a query in one pass, rather than
+    /// making the user fix and recompile one error at a time.
+    pub fn validate_all(&self) -> Vec<ValidationError<S>> {
+        let mut errors = Vec::new();
+
+        for clause in self.clauses.iter() {
+            errors.extend(clause.validate_all());
+        }
+
+        for clause in self.clauses.iter() {
+            let i = clause.head.name;
+            for &(negated, ref pred) in clause.body.iter() {
+                let j = pred.name;
+                let crosses_stratum = if negated { j >= i } else { j > i };
+                if crosses_stratum {
+                    errors.push(ValidationError::Stratification {
+                        negated,
+                        negated_span: pred.span.clone(),
+                        head_span: clause.head.span.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut arities = hashmap! {
+            -1 => 2,
+            -2 => 1,
+            -3 => 3,
+            -4 => 3,
+            -5 => 3,
+            -6 => 4,
+        };
+        for clause in self.clauses.iter() {
+            let name = clause.head.name;
+            let argn = clause.head.args.len();
+            if arities.contains_key(&name) {
+                let expected = arities[&name];
+                if expected != argn {
+                    errors.push(ValidationError::BadArgn {
+                        expected,
+                        found: argn,
+                        span: clause.head.span.clone(),
+                    });
+                }
+            } else {
+                let _ = arities.insert(name, argn);
+            }
+        }
+        for clause in self.clauses.iter() {
+            for (_, pred) in clause.body.iter() {
+                let argn = pred.args.len();
+                match arities.get(&pred.name).copied() {
+                    Some(expected) => {
+                        if expected != argn {
+                            errors.push(ValidationError::BadArgn {
+                                expected,
+                                found: argn,
+                                span: pred.span.clone(),
+                            });
+                        }
+                    }
+                    None => errors.push(ValidationError::NoSuchClause {
+                        argn,
+                        name: pred.name,
+                        span: pred.span.clone(),
+                    }),
+                }
+            }
+        }
+
+        let _ = self.goal.for_each_var(|var, span| {
+            if var >= self.goal_vars {
+                errors.push(ValidationError::VariableOutOfRange {
+                    max_vars: self.goal_vars,
+                    span: span.clone(),
+                    var,
+                });
+            }
+            Ok(())
+        });
+
+        errors
+    }
 }
 
 impl<S: Span> ValidatedClause<S> {
@@ -260,7 +351,7 @@ impl<S: Span> ValidatedClause<S> {
             if var < self.vars {
                 Ok(())
             } else {
-                Err(ValidationError::BadVariable {
+                Err(ValidationError::VariableOutOfRange {
                     max_vars: self.vars,
                     span: span.clone(),
                     var,
@@ -274,7 +365,7 @@ impl<S: Span> ValidatedClause<S> {
                 if var < self.vars {
                     Ok(())
                 } else {
-                    Err(ValidationError::BadVariable {
+                    Err(ValidationError::VariableOutOfRange {
                         max_vars: self.vars,
                         span: span.clone(),
                         var,
@@ -305,7 +396,13 @@ impl<S: Span> ValidatedClause<S> {
                         }
                     }
                     (ValidatedValueInner::Var(var), ValidatedValueInner::Str(_))
-                    | (ValidatedValueInner::Str(_), ValidatedValueInner::Var(var)) => {
+                    | (ValidatedValueInner::Str(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Int(_))
+                    | (ValidatedValueInner::Int(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Float(_))
+                    | (ValidatedValueInner::Float(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Bool(_))
+                    | (ValidatedValueInner::Bool(_), ValidatedValueInner::Var(var)) => {
                         if !*negated {
                             used_positively[*var as usize] = true;
                         }
@@ -321,8 +418,8 @@ impl<S: Span> ValidatedClause<S> {
         }
         for (var, ok) in used_positively.iter().enumerate() {
             if !ok {
-                return Err(ValidationError::NeverUsedPositively {
-                    clause: self.clone(),
+                return Err(ValidationError::UnboundVariable {
+                    span: self.span.clone(),
                     var: var as u32,
                 });
             }
@@ -330,6 +427,89 @@ impl<S: Span> ValidatedClause<S> {
 
         Ok(())
     }
+
+    /// Like `validate`, but collects every problem found instead of stopping at the first. See
+    /// `ValidatedQuery::validate_all`.
+    pub fn validate_all(&self) -> Vec<ValidationError<S>> {
+        let mut errors = Vec::new();
+
+        let _ = self.head.for_each_var(|var, span| {
+            if var >= self.vars {
+                errors.push(ValidationError::VariableOutOfRange {
+                    max_vars: self.vars,
+                    span: span.clone(),
+                    var,
+                });
+            }
+            Ok(())
+        });
+
+        for (_, pred) in self.body.iter() {
+            let _ = pred.for_each_var(|var, span| {
+                if var >= self.vars {
+                    errors.push(ValidationError::VariableOutOfRange {
+                        max_vars: self.vars,
+                        span: span.clone(),
+                        var,
+                    });
+                }
+                Ok(())
+            });
+        }
+
+        let mut used_positively = vec![false; self.vars as usize];
+        let mut eq_vars = Vec::new();
+        let mut neq_vars = Vec::new();
+        for (negated, pred) in self.body.iter() {
+            if pred.name == -1 {
+                if pred.args.len() != 2 {
+                    errors.push(ValidationError::BadArgn {
+                        expected: 2,
+                        found: pred.args.len(),
+                        span: pred.span.clone(),
+                    });
+                    continue;
+                }
+                match (&pred.args[0].inner, &pred.args[1].inner) {
+                    (ValidatedValueInner::Var(l), ValidatedValueInner::Var(r)) => {
+                        if *negated {
+                            neq_vars.push((l, r));
+                        } else {
+                            eq_vars.push((l, r));
+                        }
+                    }
+                    (ValidatedValueInner::Var(var), ValidatedValueInner::Str(_))
+                    | (ValidatedValueInner::Str(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Int(_))
+                    | (ValidatedValueInner::Int(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Float(_))
+                    | (ValidatedValueInner::Float(_), ValidatedValueInner::Var(var))
+                    | (ValidatedValueInner::Var(var), ValidatedValueInner::Bool(_))
+                    | (ValidatedValueInner::Bool(_), ValidatedValueInner::Var(var)) => {
+                        if !*negated {
+                            used_positively[*var as usize] = true;
+                        }
+                    }
+                    _ => {}
+                }
+            } else if !*negated {
+                let _ = pred.for_each_var(|var, _| {
+                    used_positively[var as usize] = true;
+                    Ok(())
+                });
+            }
+        }
+        for (var, ok) in used_positively.iter().enumerate() {
+            if !ok {
+                errors.push(ValidationError::UnboundVariable {
+                    span: self.span.clone(),
+                    var: var as u32,
+                });
+            }
+        }
+
+        errors
+    }
 }
 
 impl<S: Span> ValidatedPredicate<S> {
@@ -8,7 +8,7 @@ use crate::validated::{
 use std::{collections::HashMap, sync::Arc};
 use topological_sort::TopologicalSort;
 
-static BUILTINS: &[(&str, usize)] = &[
+pub(crate) static BUILTINS: &[(&str, usize)] = &[
     ("=", 2),
     ("atom", 1),
     ("name", 3),
@@ -25,6 +25,15 @@ pub trait ValueVisitor<'a, S: Span> {
     /// Visits with a string literal.
     fn visit_arg_string(&mut self, string: &'a str, span: S);
 
+    /// Visits with an integer literal.
+    fn visit_arg_int(&mut self, n: i64, span: S);
+
+    /// Visits with a float literal.
+    fn visit_arg_float(&mut self, n: f64, span: S);
+
+    /// Visits with a boolean literal.
+    fn visit_arg_bool(&mut self, b: bool, span: S);
+
     /// Visits with a variable.
     fn visit_arg_var(&mut self, var: &'a str, span: S);
 }
@@ -160,6 +169,30 @@ impl<'a, S: Span> ValueVisitor<'a, S> for ClauseVisitor<'a, S> {
         });
     }
 
+    /// Adds an integer literal as an argument to the head of the clause.
+    fn visit_arg_int(&mut self, n: i64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Int(n),
+            span,
+        });
+    }
+
+    /// Adds a float literal as an argument to the head of the clause.
+    fn visit_arg_float(&mut self, n: f64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Float(n),
+            span,
+        });
+    }
+
+    /// Adds a boolean literal as an argument to the head of the clause.
+    fn visit_arg_bool(&mut self, b: bool, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Bool(b),
+            span,
+        });
+    }
+
     /// Adds a variable as an argument to the head of the clause.
     fn visit_arg_var(&mut self, var: &'a str, span: S) {
         let var = self.var_pool.intern(var);
@@ -210,6 +243,30 @@ impl<'a, S: Span> ValueVisitor<'a, S> for PredicateVisitor<'a, S> {
         });
     }
 
+    /// Adds an integer literal as an argument to the predicate.
+    fn visit_arg_int(&mut self, n: i64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Int(n),
+            span,
+        });
+    }
+
+    /// Adds a float literal as an argument to the predicate.
+    fn visit_arg_float(&mut self, n: f64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Float(n),
+            span,
+        });
+    }
+
+    /// Adds a boolean literal as an argument to the predicate.
+    fn visit_arg_bool(&mut self, b: bool, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Bool(b),
+            span,
+        });
+    }
+
     /// Adds a variable as an argument to the predicate.
     fn visit_arg_var(&mut self, var: &'a str, span: S) {
         let var = self.clause_visitor.var_pool.intern(var);
@@ -267,7 +324,7 @@ impl<'a, S: Span> GoalVisitor<'a, S> {
                  }| {
                     let functor = (head.0, head.1.len());
                     let head_name = names.get(&functor).copied().ok_or_else(|| {
-                        ValidationError::NoSuchClause {
+                        ValidationError::NoSuchClauseBuilding {
                             argn: functor.1,
                             name: functor.0.to_string(),
                             span: head.2.clone(),
@@ -279,7 +336,7 @@ impl<'a, S: Span> GoalVisitor<'a, S> {
                         .map(|(negated, name, args, span)| {
                             let functor = (name, args.len());
                             let name = names.get(&functor).copied().ok_or_else(|| {
-                                ValidationError::NoSuchClause {
+                                ValidationError::NoSuchClauseBuilding {
                                     argn: functor.1,
                                     name: functor.0.to_string(),
                                     span: span.clone(),
@@ -310,7 +367,7 @@ impl<'a, S: Span> GoalVisitor<'a, S> {
         let name = names
             .get(&functor)
             .copied()
-            .ok_or_else(|| ValidationError::NoSuchClause {
+            .ok_or_else(|| ValidationError::NoSuchClauseBuilding {
                 argn: functor.1,
                 name: functor.0.to_string(),
                 span: goal_span,
@@ -348,6 +405,30 @@ impl<'a, S: Span> ValueVisitor<'a, S> for GoalVisitor<'a, S> {
         });
     }
 
+    /// Adds an integer literal as an argument to the goal.
+    fn visit_arg_int(&mut self, n: i64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Int(n),
+            span,
+        });
+    }
+
+    /// Adds a float literal as an argument to the goal.
+    fn visit_arg_float(&mut self, n: f64, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Float(n),
+            span,
+        });
+    }
+
+    /// Adds a boolean literal as an argument to the goal.
+    fn visit_arg_bool(&mut self, b: bool, span: S) {
+        self.args.push(ValidatedValue {
+            inner: ValidatedValueInner::Bool(b),
+            span,
+        });
+    }
+
     /// Adds a variable as an argument to the goal.
     fn visit_arg_var(&mut self, var: &'a str, span: S) {
         let var = self.var_pool.intern(var);
@@ -2,16 +2,83 @@
 
 extern crate proc_macro;
 
-use g1_common::proc_macro::query_proc_macro;
+use g1_common::proc_macro::{
+    diagnostic::{Diagnostic, Level},
+    query_proc_macro,
+};
 use proc_macro::TokenStream;
 use proc_macro_hack::proc_macro_hack;
-use quote::quote;
+use quote::quote_spanned;
 
 #[proc_macro_hack]
 pub fn query(input: TokenStream) -> TokenStream {
-    let output = match query_proc_macro(input.into()) {
-        Ok(toks) => toks,
-        Err(err) => quote! { compile_error!(#err)},
-    };
-    output.into()
+    match query_proc_macro(input.into()) {
+        Ok(toks) => toks.into(),
+        Err(diagnostics) => emit_diagnostics(diagnostics).into(),
+    }
+}
+
+/// Turns every `Diagnostic` into what the compiler actually sees.
+///
+/// On nightly, this emits real, span-pointed `proc_macro::Diagnostic`s (with their `Help`/`Note`
+/// children attached as sub-messages) and hands back an empty token stream, since `.emit()` is how
+/// nightly reports the problem. `proc_macro::Diagnostic` isn't available on stable, so there each
+/// one is lowered to its own span-pointed `compile_error!` invocation instead, with child messages
+/// folded into the single string `compile_error!` accepts.
+#[cfg(feature = "nightly")]
+fn emit_diagnostics(diagnostics: Vec<Diagnostic>) -> proc_macro2::TokenStream {
+    for diagnostic in diagnostics {
+        to_proc_macro_diagnostic(diagnostic).emit();
+    }
+    proc_macro2::TokenStream::new()
+}
+
+#[cfg(feature = "nightly")]
+fn to_proc_macro_diagnostic(diagnostic: Diagnostic) -> proc_macro::Diagnostic {
+    let span: proc_macro2::Span = diagnostic.span.into();
+    let level = to_proc_macro_level(diagnostic.level);
+    let mut diag = proc_macro::Diagnostic::spanned(span.unwrap(), level, diagnostic.message);
+    for child in diagnostic.children {
+        let child_span: proc_macro2::Span = child.span.into();
+        let child_span = child_span.unwrap();
+        diag = match child.level {
+            Level::Error => diag.span_error(child_span, child.message),
+            Level::Help => diag.span_help(child_span, child.message),
+            Level::Note => diag.span_note(child_span, child.message),
+        };
+    }
+    diag
+}
+
+#[cfg(feature = "nightly")]
+fn to_proc_macro_level(level: Level) -> proc_macro::Level {
+    match level {
+        Level::Error => proc_macro::Level::Error,
+        Level::Help => proc_macro::Level::Help,
+        Level::Note => proc_macro::Level::Note,
+    }
+}
+
+#[cfg(not(feature = "nightly"))]
+fn emit_diagnostics(diagnostics: Vec<Diagnostic>) -> proc_macro2::TokenStream {
+    diagnostics.into_iter().map(to_compile_error).collect()
+}
+
+#[cfg(not(feature = "nightly"))]
+fn to_compile_error(diagnostic: Diagnostic) -> proc_macro2::TokenStream {
+    let span: proc_macro2::Span = diagnostic.span.into();
+    let mut message = diagnostic.message;
+    for child in &diagnostic.children {
+        message.push_str(&format!("\n{}: {}", level_label(child.level), child.message));
+    }
+    quote_spanned! { span => compile_error!(#message); }
+}
+
+#[cfg(not(feature = "nightly"))]
+fn level_label(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Help => "help",
+        Level::Note => "note",
+    }
 }
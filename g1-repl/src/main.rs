@@ -0,0 +1,154 @@
+//! A standalone REPL for experimenting with the G1 query language.
+//!
+//! Unlike `g1-cli`'s `ReplSqlite` subcommand, this doesn't talk to a `Connection` -- it's backed
+//! by `ValidatedQuery::solve_selfcontained`, so it's meant for playing with the grammar and
+//! quickly trying out rules rather than for working with a real database.
+
+use anyhow::Result;
+use directories::BaseDirs;
+use g1_common::proc_macro::{ir::Query, OffsetSpan};
+use linefeed::{Interface, ReadResult};
+use std::sync::Arc;
+
+fn main() -> Result<()> {
+    let reader = Interface::new("g1-repl")?;
+    let history_path = BaseDirs::new().map(|bd| bd.cache_dir().join("g1_repl_history"));
+    if let Some(path) = history_path.as_ref() {
+        if let Err(err) = reader.load_history(path) {
+            log::debug!("Failed to load history: {}", err);
+        }
+    }
+
+    let mut edb = String::new();
+    let mut pending = String::new();
+    loop {
+        reader.set_prompt(if pending.is_empty() { "g1> " } else { "...> " })?;
+
+        let line = match reader.read_line()? {
+            ReadResult::Input(line) => line,
+            _ => break,
+        };
+        reader.add_history_unique(line.clone());
+        if let Some(path) = history_path.as_ref() {
+            if let Err(err) = reader.save_history(path) {
+                log::debug!("Failed to save history: {}", err);
+            }
+        }
+
+        pending.push_str(&line);
+        pending.push('\n');
+
+        // Keep re-prompting with a continuation prompt until the clause or goal is syntactically
+        // complete -- i.e. parens are balanced and it ends with a terminating `.`.
+        if !is_complete(&pending) {
+            continue;
+        }
+        let unit = pending.trim().to_string();
+        pending.clear();
+
+        if unit.trim_start().starts_with("?-") {
+            if let Err(err) = run_goal(&edb, &unit) {
+                println!("Error: {}", err);
+            }
+        } else {
+            // Defer validation until a goal is actually entered, so a clause that calls a
+            // not-yet-defined predicate is fine as long as the predicate shows up before the next
+            // `?- ...` line.
+            edb.push_str(&unit);
+            edb.push('\n');
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether `src` is a syntactically complete clause or goal: parens are balanced outside of
+/// string literals, and the last non-whitespace character is a terminating `.`.
+fn is_complete(src: &str) -> bool {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut last_significant = None;
+    for ch in src.chars() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            _ if in_string || ch.is_whitespace() => {}
+            _ => last_significant = Some(ch),
+        }
+    }
+    depth <= 0 && last_significant == Some('.')
+}
+
+fn run_goal(edb: &str, goal: &str) -> Result<()> {
+    let src = format!("{}\n{}", edb, goal);
+    let query = Query::parse_str(&src).map_err(|err| anyhow::anyhow!("{}", err))?;
+    let query = query
+        .to_validated()
+        .map_err(|err| anyhow::anyhow!("{}", err))?;
+    if let Err(err) = query.validate() {
+        // Carry the proc-macro spans over to line/column offsets so the error can point at the
+        // offending predicate or variable in `src`, rather than just printing its message.
+        let err = err.map_span(&mut OffsetSpan::from);
+        return Err(anyhow::anyhow!("{}", render_diagnostic(&src, &err)));
+    }
+
+    print_solns(&query.solve_selfcontained());
+    Ok(())
+}
+
+/// Renders a `ValidationError<OffsetSpan>` as its message, followed by a caret-underlined snippet
+/// of the source line each of the error's spans starts on. A `Stratification` error carries two
+/// spans -- the offending body reference and the clause head it illegally depends on -- so both
+/// get their own snippet, in the style of a compiler pointing "this reference flows here" at both
+/// ends of the illegal dependency.
+fn render_diagnostic(src: &str, err: &g1_common::validated::ValidationError<OffsetSpan>) -> String {
+    let spans: Vec<OffsetSpan> = match err {
+        g1_common::validated::ValidationError::BadArgn { span, .. }
+        | g1_common::validated::ValidationError::NoSuchClause { span, .. }
+        | g1_common::validated::ValidationError::NoSuchClauseBuilding { span, .. }
+        | g1_common::validated::ValidationError::UnboundVariable { span, .. }
+        | g1_common::validated::ValidationError::VariableOutOfRange { span, .. } => vec![*span],
+        g1_common::validated::ValidationError::Stratification {
+            negated_span,
+            head_span,
+            ..
+        } => vec![*negated_span, *head_span],
+        g1_common::validated::ValidationError::IllegalRecursion => Vec::new(),
+    };
+
+    if spans.is_empty() {
+        return err.to_string();
+    }
+
+    let mut out = err.to_string();
+    for span in spans {
+        let line = src.lines().nth(span.start.line.saturating_sub(1)).unwrap_or("");
+        let caret = " ".repeat(span.start.column) + "^";
+        out.push_str(&format!("\n{}\n{}", line, caret));
+    }
+    out
+}
+
+fn print_solns(solns: &[Vec<Arc<str>>]) {
+    println!("Got {} results:", solns.len());
+    for soln in solns {
+        print!("(");
+        let mut first = true;
+        for s in soln {
+            if first {
+                first = false;
+            } else {
+                print!(", ");
+            }
+            print!("{:?}", s);
+        }
+        println!(")");
+    }
+}
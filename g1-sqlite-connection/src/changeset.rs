@@ -0,0 +1,32 @@
+//! The changeset format `SqliteConnection::export_changeset`/`apply_changeset` exchange, so two
+//! stores can synchronize by shipping the bytes one produces to the other's `apply_changeset`.
+//!
+//! Each row the writer thread appends to `changelog` is one `ChangeEntry` -- a concretely-valued
+//! counterpart to `g1_common::Mutation` recording exactly what was applied (in particular,
+//! `CreateAtom` carries the atom that was actually inserted, since `Mutation::CreateAtom` only
+//! says "make up a fresh one" and replaying that literally would mint a different atom on the
+//! receiving side).
+
+pub use g1_common::ChangeEntry;
+use serde_derive::{Deserialize, Serialize};
+
+/// A changeset as exchanged between `export_changeset` and `apply_changeset`: every `ChangeEntry`
+/// recorded after `since`, plus the cursor a later `export_changeset` call should be given to pick
+/// up right after them.
+#[derive(Deserialize, Serialize)]
+struct Payload {
+    since: u64,
+    entries: Vec<ChangeEntry>,
+}
+
+/// Encodes a changeset: `since` is the cursor a later `export_changeset` call should pass in to
+/// resume right after `entries`.
+pub fn encode(since: u64, entries: Vec<ChangeEntry>) -> Vec<u8> {
+    serde_json::to_vec(&Payload { since, entries }).expect("ChangeEntry always serializes")
+}
+
+/// The inverse of `encode`: the new `since` cursor, and the entries to replay in order.
+pub fn decode(bytes: &[u8]) -> Result<(u64, Vec<ChangeEntry>), serde_json::Error> {
+    let payload: Payload = serde_json::from_slice(bytes)?;
+    Ok((payload.since, payload.entries))
+}
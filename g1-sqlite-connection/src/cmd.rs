@@ -1,7 +1,123 @@
-use crate::SqliteConnectionError;
-use g1_common::{nameless::NamelessQuery, Atom, Hash, Mime};
-use std::sync::Arc;
-use tokio::sync::oneshot::Sender;
+use crate::{changeset::ChangeEntry, SqliteConnectionError};
+use g1_common::{
+    nameless::NamelessQuery, Atom, Bytes, Hash, Mime, Mutation, MutationResult, TagValue,
+};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::{broadcast, oneshot::Sender};
+
+/// Called after every step of a `Command::Backup` with `(remaining, total)` pages left to copy.
+pub type BackupProgress = Box<dyn FnMut(i32, i32) + Send>;
+
+/// Tunables for `Command::Backup`'s step loop.
+///
+/// SQLite's online backup API copies the database incrementally under a shared lock, so a writer
+/// can keep making progress between steps -- `pages_per_step` bounds how much of the lock a single
+/// step holds, and `pause` gives concurrent writers a wider window to run between steps, at the
+/// cost of the backup taking longer (and, if the source keeps changing, possibly restarting its
+/// page count more times before finishing).
+#[derive(Clone, Copy, Debug)]
+pub struct BackupOptions {
+    /// How many pages to copy per step.
+    pub pages_per_step: i32,
+    /// How long to sleep between steps.
+    pub pause: Duration,
+}
+
+impl Default for BackupOptions {
+    fn default() -> BackupOptions {
+        BackupOptions {
+            pages_per_step: 100,
+            pause: Duration::from_secs(0),
+        }
+    }
+}
+
+/// What `Command::GcBlobs` reclaimed: blob files on disk with no row referencing them (left behind
+/// by a `store_blob` that crashed before its matching `CreateBlob` landed), and rows referencing a
+/// hash with no file on disk (e.g. removed out-of-band).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GcReport {
+    /// The hash and size in bytes of each blob file reclaimed because its last referencing row
+    /// was gone.
+    pub reclaimed: Vec<(Hash, u64)>,
+
+    /// How many rows referencing a missing file were removed from the `blobs` table.
+    pub removed_rows: usize,
+}
+
+/// Called by `apply_changeset` when a `ChangeEntry` conflicts with data already present (e.g. a
+/// `CreateName` whose `(ns, title)` was independently created on both sides), given the entry and
+/// the error applying it produced, to decide whether to give up on the whole changeset or leave
+/// the existing data alone and move on.
+pub type ConflictResolver = Box<dyn FnMut(&ChangeEntry, &rusqlite::Error) -> ConflictAction + Send>;
+
+/// What `apply_changeset` should do with a `ChangeEntry` that conflicted with existing data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictAction {
+    /// Leave the existing, conflicting data alone and move on to the next entry.
+    Skip,
+    /// Abort the whole changeset; nothing from it is applied.
+    Abort,
+}
+
+/// What `apply_changeset` did with a changeset's entries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChangesetApplyReport {
+    /// How many entries were applied.
+    pub applied: usize,
+    /// How many entries were skipped by a `ConflictResolver` returning `ConflictAction::Skip`.
+    pub skipped: usize,
+}
+
+/// Which columns a row passed to `Command::Import` is interpreted as.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImportKind {
+    /// One column: an external key to create an atom for, with no other data.
+    Atom,
+    /// Three columns: `atom, ns, title`.
+    Name,
+    /// Three columns: `from, to, label`.
+    Edge,
+    /// Three columns: `atom, key, value`.
+    Tag,
+}
+
+impl ImportKind {
+    /// How many columns a row of this kind has.
+    pub fn columns(self) -> usize {
+        match self {
+            ImportKind::Atom => 1,
+            ImportKind::Name | ImportKind::Edge | ImportKind::Tag => 3,
+        }
+    }
+}
+
+impl std::str::FromStr for ImportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImportKind, String> {
+        match s {
+            "atom" => Ok(ImportKind::Atom),
+            "name" => Ok(ImportKind::Name),
+            "edge" => Ok(ImportKind::Edge),
+            "tag" => Ok(ImportKind::Tag),
+            _ => Err(format!(
+                "unknown import kind {:?} (expected atom, name, edge, or tag)",
+                s
+            )),
+        }
+    }
+}
+
+/// What `Command::Import` did with a file's rows.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ImportReport {
+    /// How many rows (including atoms auto-created for an external key seen for the first time)
+    /// were newly inserted.
+    pub inserted: usize,
+    /// How many rows were already present and so left alone.
+    pub skipped: usize,
+}
 
 #[derive(derivative::Derivative)]
 #[derivative(Debug)]
@@ -38,7 +154,7 @@ pub enum Command {
     CreateTag(
         Atom,
         String,
-        String,
+        TagValue,
         bool,
         #[derivative(Debug = "ignore")] Sender<Result<bool, SqliteConnectionError>>,
     ),
@@ -66,4 +182,38 @@ pub enum Command {
         NamelessQuery,
         #[derivative(Debug = "ignore")] Sender<Result<Vec<Vec<Arc<str>>>, SqliteConnectionError>>,
     ),
+    Batch(
+        Vec<Mutation>,
+        #[derivative(Debug = "ignore")] Sender<Result<Vec<MutationResult>, SqliteConnectionError>>,
+    ),
+    Backup(
+        PathBuf,
+        BackupOptions,
+        #[derivative(Debug = "ignore")] Option<BackupProgress>,
+        #[derivative(Debug = "ignore")] Sender<Result<(), SqliteConnectionError>>,
+    ),
+    GcBlobs(#[derivative(Debug = "ignore")] Sender<Result<GcReport, SqliteConnectionError>>),
+    ExportChangeset(
+        u64,
+        #[derivative(Debug = "ignore")] Sender<Result<Bytes, SqliteConnectionError>>,
+    ),
+    ApplyChangeset(
+        Vec<ChangeEntry>,
+        #[derivative(Debug = "ignore")] Option<ConflictResolver>,
+        #[derivative(Debug = "ignore")]
+        Sender<Result<ChangesetApplyReport, SqliteConnectionError>>,
+    ),
+    Subscribe(
+        #[derivative(Debug = "ignore")]
+        Sender<Result<broadcast::Receiver<ChangeEntry>, SqliteConnectionError>>,
+    ),
+    ExportPortable(
+        PathBuf,
+        #[derivative(Debug = "ignore")] Sender<Result<(), SqliteConnectionError>>,
+    ),
+    Import(
+        ImportKind,
+        Vec<Vec<String>>,
+        #[derivative(Debug = "ignore")] Sender<Result<ImportReport, SqliteConnectionError>>,
+    ),
 }
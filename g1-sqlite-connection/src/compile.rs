@@ -0,0 +1,291 @@
+//! Compiles a `NamelessQuery` directly to a single SQL statement, instead of pulling every row
+//! into memory and running `g1_common::naive_solve` over it.
+//!
+//! Each derived predicate becomes a `WITH` common table expression, emitted in the same
+//! dependency order `NamelessQuery::clauses` already stratifies them in -- `NamelessQuery`'s own
+//! `validate` already guarantees a body predicate only ever refers to its own predicate (for
+//! self-recursion) or a strictly earlier one (always true for negated body predicates), so by the
+//! time a CTE is emitted, every predicate it can reference is already in scope as either a base
+//! table or an earlier CTE. A predicate whose clauses self-recurse becomes a `WITH RECURSIVE` term
+//! so SQLite's own fixpoint engine computes the transitive closure (e.g. the `path` example)
+//! instead of us looping `naive_solve`-style in Rust.
+
+use g1_common::nameless::{
+    NamelessClause, NamelessPredicate, NamelessQuery, NamelessValue, FIRST_IDB_PRED,
+};
+
+/// The base relations the builtin predicates `atom/1`, `name/3`, `edge/3`, `tag/3`, and `blob/4`
+/// (predicate numbers `0`-`4`) are backed by.
+fn builtin_table(name: u32) -> Option<(&'static str, &'static [&'static str])> {
+    match name {
+        0 => Some(("atoms", &["atom"])),
+        1 => Some(("names", &["atom", "ns", "title"])),
+        2 => Some(("edges", &["edge_from", "edge_to", "label"])),
+        3 => Some(("tags", &["atom", "key", "value"])),
+        4 => Some(("blobs", &["atom", "kind", "mime", "hash"])),
+        _ => None,
+    }
+}
+
+/// The table or CTE name, and column names, a predicate's rows can be read from.
+fn table_ref(name: u32, arities: &[usize]) -> (String, Vec<String>) {
+    if let Some((table, columns)) = builtin_table(name) {
+        return (
+            table.to_string(),
+            columns.iter().map(|c| c.to_string()).collect(),
+        );
+    }
+    let arity = arities[(name - FIRST_IDB_PRED) as usize];
+    (
+        cte_name(name),
+        (0..arity).map(|i| format!("c{}", i)).collect(),
+    )
+}
+
+fn cte_name(name: u32) -> String {
+    format!("p{}", name)
+}
+
+/// The per-body-predicate WHERE conditions and variable bindings for one reference to `pred`,
+/// aliased as `alias` in the FROM/JOIN list.
+///
+/// Returns the list of SQL conditions to AND together, and, for every variable this is the first
+/// occurrence of, the SQL expression (`{alias}.{column}`) later occurrences and the head/goal
+/// projection should read that variable's value from.
+fn predicate_conditions(
+    pred: &NamelessPredicate,
+    alias: &str,
+    arities: &[usize],
+    bound: &mut Vec<Option<String>>,
+    params: &mut Vec<String>,
+) -> (Vec<String>, Vec<String>) {
+    let (_, columns) = table_ref(pred.name, arities);
+    let mut conds = Vec::new();
+    let mut new_bindings = Vec::new();
+    for (column, arg) in columns.iter().zip(pred.args.iter()) {
+        let col_expr = format!("{}.{}", alias, column);
+        match arg {
+            NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
+            NamelessValue::Var(n) => {
+                let n = *n as usize;
+                if n >= bound.len() {
+                    bound.resize(n + 1, None);
+                }
+                match &bound[n] {
+                    Some(existing) => conds.push(format!("{} = {}", col_expr, existing)),
+                    None => {
+                        bound[n] = Some(col_expr.clone());
+                        new_bindings.push(col_expr);
+                    }
+                }
+            }
+            lit => {
+                conds.push(format!("{} = ?", col_expr));
+                params.push(literal_param_text(lit));
+            }
+        }
+    }
+    (conds, new_bindings)
+}
+
+/// Compiles one clause (a rule contributing to a predicate's CTE) to a single `SELECT`.
+fn compile_clause(clause: &NamelessClause, arities: &[usize], params: &mut Vec<String>) -> String {
+    let first_param = params.len();
+    let mut bound: Vec<Option<String>> = vec![None; clause.vars as usize];
+    let mut froms = Vec::new();
+    let mut wheres = Vec::new();
+
+    for (i, pred) in clause.body_pos.iter().enumerate() {
+        let alias = format!("b{}", i);
+        let (table, _) = table_ref(pred.name, arities);
+        froms.push(format!("{} AS {}", table, alias));
+        let (conds, _) = predicate_conditions(pred, &alias, arities, &mut bound, params);
+        wheres.extend(conds);
+    }
+
+    // Every variable used in the body must already be bound by the time we get to the negated
+    // predicates and the head, since `NamelessClause::validate` requires negated/head variables
+    // to appear positively somewhere in `body_pos`.
+    for (i, pred) in clause.body_neg.iter().enumerate() {
+        let alias = format!("n{}", i);
+        let (table, _) = table_ref(pred.name, arities);
+        let mut neg_bound = bound.clone();
+        let (conds, _) = predicate_conditions(pred, &alias, arities, &mut neg_bound, params);
+        wheres.push(format!(
+            "NOT EXISTS (SELECT 1 FROM {} AS {} WHERE {})",
+            table,
+            alias,
+            conds.join(" AND ")
+        ));
+    }
+
+    for (negated, pred) in &clause.body_filters {
+        let lhs = filter_value_expr(&pred.args[0], &bound, params);
+        let rhs = filter_value_expr(&pred.args[1], &bound, params);
+        let op = match pred.name {
+            5 => "=",
+            6 => "<",
+            7 => "<=",
+            n => panic!("unknown comparison builtin: {}", n),
+        };
+        let cond = format!("{} {} {}", lhs, op, rhs);
+        wheres.push(if *negated { format!("NOT ({})", cond) } else { cond });
+    }
+
+    let mut head_params = Vec::new();
+    let select = clause
+        .head
+        .iter()
+        .map(|arg| match arg {
+            NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
+            NamelessValue::Var(n) => bound[*n as usize]
+                .clone()
+                .expect("head variable not bound by a positive body predicate"),
+            lit => {
+                head_params.push(literal_param_text(lit));
+                "?".to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    // The SELECT list comes before FROM/WHERE in the SQL, so its parameters go first too.
+    let _ = params.splice(first_param..first_param, head_params);
+
+    let froms = if froms.is_empty() {
+        String::new()
+    } else {
+        format!(" FROM {}", froms.join(", "))
+    };
+    let wheres = if wheres.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", wheres.join(" AND "))
+    };
+
+    format!("SELECT {}{}{}", select, froms, wheres)
+}
+
+/// Resolves a comparison-builtin argument to a SQL expression. Unlike `predicate_conditions`, this
+/// never binds a new variable -- a comparison builtin only filters bindings `body_pos` already
+/// established, so every variable it refers to must already be in `bound`.
+fn filter_value_expr(
+    arg: &NamelessValue,
+    bound: &[Option<String>],
+    params: &mut Vec<String>,
+) -> String {
+    match arg {
+        NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
+        NamelessValue::Var(n) => bound[*n as usize]
+            .clone()
+            .expect("comparison-builtin variable not bound by a positive body predicate"),
+        lit => {
+            params.push(literal_param_text(lit));
+            "?".to_string()
+        }
+    }
+}
+
+/// Renders a non-`Var`, non-`MetaVar` `NamelessValue` to the text form it should be bound as a SQL
+/// parameter -- every base table column is still plain text end to end (see
+/// `g1_common::naive_solve::literal_text` for the same caveat on the in-memory solver), so a typed
+/// literal compares against stored data the same way a bare `Str` always has.
+fn literal_param_text(v: &NamelessValue) -> String {
+    match v {
+        NamelessValue::Str(s) => s.to_string(),
+        NamelessValue::Int(n) => n.to_string(),
+        NamelessValue::Float(n) => n.to_string(),
+        NamelessValue::Bool(b) => b.to_string(),
+        NamelessValue::MetaVar(v) => panic!("unfilled metavariable: ${}", v),
+        NamelessValue::Var(_) => panic!("literal_param_text called with a Var"),
+    }
+}
+
+/// Compiles every clause contributing to predicate `pred_id`'s CTE, returning `(columns, body,
+/// self_recursive)`.
+fn compile_predicate(
+    pred_id: u32,
+    clauses: &[NamelessClause],
+    arities: &[usize],
+    params: &mut Vec<String>,
+) -> (Vec<String>, String, bool) {
+    let arity = clauses
+        .first()
+        .map(|c| c.head.len())
+        .unwrap_or_default();
+    let columns = (0..arity).map(|i| format!("c{}", i)).collect::<Vec<_>>();
+
+    let self_recursive = clauses
+        .iter()
+        .any(|c| c.body_pos.iter().any(|p| p.name == pred_id));
+
+    let selects = clauses
+        .iter()
+        .map(|c| compile_clause(c, arities, params))
+        .collect::<Vec<_>>();
+
+    (columns, selects.join(" UNION "), self_recursive)
+}
+
+/// Compiles `query` into a single SQL statement and its bound parameters (in order), selecting
+/// every column of whatever table/CTE the goal predicate resolves to, filtered by the goal's own
+/// arguments, with `limit` applied as a trailing `LIMIT`.
+pub fn compile(query: &NamelessQuery, limit: Option<usize>) -> (String, Vec<String>) {
+    let arities = query
+        .clauses
+        .iter()
+        .map(|clauses| clauses.first().map(|c| c.head.len()).unwrap_or_default())
+        .collect::<Vec<_>>();
+
+    let mut params = Vec::new();
+    let mut ctes = Vec::new();
+    let mut any_recursive = false;
+
+    for (i, clauses) in query.clauses.iter().enumerate() {
+        let pred_id = i as u32 + FIRST_IDB_PRED;
+        let (columns, body, self_recursive) =
+            compile_predicate(pred_id, clauses, &arities, &mut params);
+        any_recursive |= self_recursive;
+        ctes.push(format!(
+            "{}({}) AS ({})",
+            cte_name(pred_id),
+            columns.join(", "),
+            body
+        ));
+    }
+
+    let (goal_table, goal_columns) = table_ref(query.goal.name, &arities);
+    let mut bound: Vec<Option<String>> = vec![None; query.goal_vars as usize];
+    let (goal_conds, _) =
+        predicate_conditions(&query.goal, "g", &arities, &mut bound, &mut params);
+    let goal_select = format!(
+        "SELECT {} FROM {} AS g{}",
+        goal_columns
+            .iter()
+            .map(|c| format!("g.{}", c))
+            .collect::<Vec<_>>()
+            .join(", "),
+        goal_table,
+        if goal_conds.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", goal_conds.join(" AND "))
+        }
+    );
+
+    let sql = if ctes.is_empty() {
+        goal_select
+    } else {
+        format!(
+            "WITH {}{} {}",
+            if any_recursive { "RECURSIVE " } else { "" },
+            ctes.join(", "),
+            goal_select
+        )
+    };
+    let sql = match limit {
+        Some(limit) => format!("{} LIMIT {}", sql, limit),
+        None => sql,
+    };
+
+    (sql, params)
+}
@@ -31,27 +31,50 @@
     while_true
 )]
 
+mod changeset;
 mod cmd;
+mod compile;
+#[cfg(test)]
+mod compile_tests;
+#[cfg(test)]
+mod fetch_blob_range_tests;
 mod run;
 
-use crate::cmd::Command;
+pub use crate::{
+    changeset::ChangeEntry,
+    cmd::{BackupOptions, ImportKind, ImportReport},
+};
+pub use g1_common::ChangeFilter;
+
+use crate::cmd::{BackupProgress, ChangesetApplyReport, Command, ConflictResolver, GcReport};
 use bytes::BytesMut;
-use futures::{executor::block_on, prelude::*};
-use g1_common::{nameless::NamelessQuery, Atom, Bytes, Connection, Hash, Mime};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use futures::{executor::block_on, future, prelude::*};
+use g1_common::{
+    nameless::NamelessQuery, Atom, Bytes, Connection, Hash, Mime, Mutation, MutationResult,
+    TagValue,
+};
+use rand::{rngs::OsRng, RngCore};
 use sha2::{Digest, Sha256};
 use std::{
+    io::SeekFrom,
     os::unix::ffi::OsStrExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
     thread::{spawn, JoinHandle},
+    time::Duration,
 };
 use thiserror::Error;
 use tokio::{
-    fs::{create_dir_all, rename, File},
-    io::AsyncRead,
+    fs::{copy, create_dir_all, hard_link, read_dir, rename, File},
+    io::{AsyncRead, AsyncSeekExt, AsyncWrite},
     prelude::*,
     sync::{
+        broadcast,
         mpsc::{channel, Sender},
         oneshot, Mutex,
     },
@@ -62,11 +85,86 @@ use uuid::Uuid;
 /// A G1 connection based on an SQLite database, using the FS for blobs.
 ///
 /// TODO: Make this not use `g1_common::naive_solve`...
-#[derive(Debug)]
+#[derive(derivative::Derivative)]
+#[derivative(Debug)]
 pub struct SqliteConnection {
     join: JoinHandle<()>,
+    #[derivative(Debug = "ignore")]
+    reader_joins: Vec<JoinHandle<()>>,
     path: PathBuf,
-    send: Mutex<Sender<Command>>,
+
+    /// Mutations (and anything else that needs the one writable connection, e.g. `Backup`) go
+    /// through this -- serialized, same as before the reader pool existed.
+    write_send: Mutex<Sender<Command>>,
+
+    /// Read-only commands (`Query` and `ExportChangeset`) go through this instead: it's shared by a
+    /// small pool of reader threads, each with its own read-only connection, so concurrent reads
+    /// run in parallel with each other and with the writer instead of queueing behind it.
+    read_send: Sender<Command>,
+
+    /// Set by `open_encrypted`; when present, `store_blob`/`fetch_blob` transparently encrypt and
+    /// decrypt blob contents with this, keyed from (a hash of) the master key so the key used for
+    /// blobs differs from the literal key handed to SQLCipher.
+    #[derivative(Debug = "ignore")]
+    blob_cipher: Option<Arc<ChaCha20Poly1305>>,
+}
+
+/// How many read-only connections the reader pool keeps open.
+const READER_POOL_SIZE: usize = 4;
+
+/// How many not-yet-delivered `ChangeEntry`s a `subscribe` stream buffers before it starts lagging
+/// (see `tokio::sync::broadcast`'s docs) -- past this, a slow subscriber silently skips ahead to
+/// the oldest event still buffered rather than ever erroring the whole subscription.
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Concurrency tuning applied to every pooled connection `open_with` sets up -- the writer and
+/// every reader-pool connection alike.
+#[derive(Clone, Copy, Debug)]
+pub struct SqliteOptions {
+    /// How long SQLite should retry against a locked database before giving up and returning
+    /// `SQLITE_BUSY`, instead of erroring immediately (`PRAGMA busy_timeout`). Worth raising above
+    /// the default of zero whenever more than one process might touch the same store at once.
+    pub busy_timeout: Duration,
+
+    /// Whether to enforce `PRAGMA foreign_keys`. This schema doesn't currently declare any foreign
+    /// keys, so this has no effect yet, but it's here for callers relying on future ones.
+    pub foreign_keys: bool,
+
+    /// Whether to use WAL journal mode instead of SQLite's default rollback journal. WAL is what
+    /// lets the reader pool read concurrently with the writer rather than queueing behind it, so
+    /// `Default` enables it.
+    pub wal: bool,
+}
+
+impl Default for SqliteOptions {
+    fn default() -> SqliteOptions {
+        SqliteOptions {
+            busy_timeout: Duration::from_secs(0),
+            foreign_keys: false,
+            wal: true,
+        }
+    }
+}
+
+/// The per-connection `PRAGMA` statements `options` corresponds to -- applied to every connection
+/// (writer and reader pool alike) right after it's opened.
+fn options_pragma(options: &SqliteOptions) -> String {
+    format!(
+        "PRAGMA busy_timeout={};\nPRAGMA foreign_keys={};",
+        options.busy_timeout.as_millis(),
+        if options.foreign_keys { "ON" } else { "OFF" },
+    )
+}
+
+/// `journal_mode` is a property of the database file, not of any one connection, so only the
+/// writer (the one connection open when the file might not exist yet) needs to set it -- and it
+/// can't be set at all over a read-only connection, which is all the reader pool ever opens.
+fn journal_mode_pragma(options: &SqliteOptions) -> &'static str {
+    if options.wal {
+        "PRAGMA journal_mode=WAL;"
+    } else {
+        ""
+    }
 }
 
 const INITDB: &str = r#"
@@ -90,6 +188,7 @@ create table if not exists tags
   ( atom text not null
   , key text not null
   , value text not null
+  , value_kind text not null default 'str'
   , constraint tagUnique unique (atom, key)
   );
 create table if not exists blobs
@@ -98,61 +197,480 @@ create table if not exists blobs
   , mime text not null
   , hash text not null
   , constraint blobUnique unique (atom, kind, mime)
-  );"#;
+  );
+create table if not exists changelog
+  ( seq integer primary key autoincrement
+  , entry text not null
+  );
+create index if not exists namesAtomIdx on names (atom);
+create index if not exists edgesToIdx on edges (edge_to);
+create index if not exists tagsKeyIdx on tags (key);
+create index if not exists blobsHashIdx on blobs (hash);"#;
 
 impl SqliteConnection {
     /// Opens a connection to the database, given a directory to store the database and blobs in.
     pub async fn open(path: PathBuf) -> Result<SqliteConnection, SqliteConnectionError> {
+        SqliteConnection::open_impl(path, None, SqliteOptions::default()).await
+    }
+
+    /// Like `open`, but with non-default busy-timeout, foreign-key, and journal-mode tuning -- see
+    /// `SqliteOptions`. Useful when multiple processes (or multiple `g1-cli` invocations) share one
+    /// store, where `open`'s fail-fast default turns routine contention into hard errors.
+    pub async fn open_with(
+        path: PathBuf,
+        options: SqliteOptions,
+    ) -> Result<SqliteConnection, SqliteConnectionError> {
+        SqliteConnection::open_impl(path, None, options).await
+    }
+
+    /// Like `open`, but the database is opened with SQLCipher (issuing the key pragma before
+    /// running `INITDB`) and blobs are transparently encrypted on disk with an AEAD key derived
+    /// from `key`.
+    ///
+    /// The content hash used to address a blob is still computed over the plaintext, so
+    /// deduplication and `create_blob` semantics are unchanged between encrypted and
+    /// unencrypted stores -- only the bytes on disk under `blobs/` differ.
+    ///
+    /// Requires rusqlite's `sqlcipher` feature (linking against libsqlcipher instead of libsqlite3).
+    pub async fn open_encrypted(
+        path: PathBuf,
+        key: &[u8],
+    ) -> Result<SqliteConnection, SqliteConnectionError> {
+        SqliteConnection::open_impl(path, Some(key.to_vec()), SqliteOptions::default()).await
+    }
+
+    async fn open_impl(
+        path: PathBuf,
+        key: Option<Vec<u8>>,
+        options: SqliteOptions,
+    ) -> Result<SqliteConnection, SqliteConnectionError> {
         create_dir_all(path.join("blobs")).await?;
         create_dir_all(path.join("tmp")).await?;
 
+        let blob_cipher = key.as_deref().map(|key| {
+            Arc::new(ChaCha20Poly1305::new(Key::from_slice(&derive_blob_key(
+                key,
+            ))))
+        });
+
         let mut conn_path = path.clone();
         conn_path.push("g1.db");
+        let key_for_reader = key.clone();
         let conn = spawn_blocking(move || -> rusqlite::Result<rusqlite::Connection> {
-            let conn = rusqlite::Connection::open(conn_path)?;
+            let conn = rusqlite::Connection::open(&conn_path)?;
+            if let Some(key) = &key {
+                conn.execute_batch(&key_pragma(key))?;
+            }
+            conn.execute_batch(&options_pragma(&options))?;
+            conn.execute_batch(journal_mode_pragma(&options))?;
             conn.execute_batch(INITDB)?;
             Ok(conn)
         })
         .await
         .map_err(tokio::io::Error::from)??;
 
-        let (send, mut recv) = channel::<Command>(1);
-        let join = spawn(move || {
-            let mut conn = conn;
+        let blobs_dir = path.join("blobs");
 
-            while let Some(cmd) = block_on(recv.recv()) {
-                cmd.run(&mut conn);
-            }
+        let (changes_send, _) = broadcast::channel::<ChangeEntry>(CHANGE_CHANNEL_CAPACITY);
 
-            for _ in 0..3 {
-                match conn.close() {
-                    Ok(()) => break,
-                    Err((c, err)) => {
-                        conn = c;
-                        log::error!("Failed to close SQLite: {}", err);
+        let (write_send, mut write_recv) = channel::<Command>(1);
+        let join = spawn({
+            let blobs_dir = blobs_dir.clone();
+            let changes_send = changes_send.clone();
+            move || {
+                let mut conn = conn;
+
+                while let Some(cmd) = block_on(write_recv.recv()) {
+                    cmd.run(&mut conn, &blobs_dir, &changes_send);
+                }
+
+                for _ in 0..3 {
+                    match conn.close() {
+                        Ok(()) => break,
+                        Err((c, err)) => {
+                            conn = c;
+                            log::error!("Failed to close SQLite: {}", err);
+                        }
                     }
                 }
             }
         });
+
+        let mut reader_conn_path = path.clone();
+        reader_conn_path.push("g1.db");
+        let (read_send, read_recv) = channel::<Command>(1);
+        let read_recv = Arc::new(Mutex::new(read_recv));
+        let reader_joins = (0..READER_POOL_SIZE)
+            .map(|_| {
+                let conn_path = reader_conn_path.clone();
+                let key = key_for_reader.clone();
+                let read_recv = Arc::clone(&read_recv);
+                let blobs_dir = blobs_dir.clone();
+                // The reader pool only ever receives `Command::Query` and
+                // `Command::ExportChangeset`, never a mutation or `Subscribe`, but `Command::run`'s signature is shared with the writer -- this
+                // sender just goes unused here.
+                let changes_send = changes_send.clone();
+                spawn(move || {
+                    let mut conn = match rusqlite::Connection::open_with_flags(
+                        &conn_path,
+                        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+                    ) {
+                        Ok(conn) => conn,
+                        Err(err) => {
+                            log::error!("Failed to open a reader connection: {}", err);
+                            return;
+                        }
+                    };
+                    if let Some(key) = &key {
+                        if let Err(err) = conn.execute_batch(&key_pragma(key)) {
+                            log::error!("Failed to key a reader connection: {}", err);
+                            return;
+                        }
+                    }
+                    if let Err(err) = conn.execute_batch(&options_pragma(&options)) {
+                        log::error!("Failed to tune a reader connection: {}", err);
+                        return;
+                    }
+
+                    loop {
+                        let cmd = block_on(async { read_recv.lock().await.recv().await });
+                        match cmd {
+                            Some(cmd) => cmd.run(&mut conn, &blobs_dir, &changes_send),
+                            None => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
         Ok(SqliteConnection {
             join,
+            reader_joins,
             path,
-            send: Mutex::new(send),
+            write_send: Mutex::new(write_send),
+            read_send,
+            blob_cipher,
         })
     }
 
-    async fn send_command<F, T>(&self, make_command: F) -> Result<T, SqliteConnectionError>
+    async fn send_write_command<F, T>(&self, make_command: F) -> Result<T, SqliteConnectionError>
     where
         F: FnOnce(oneshot::Sender<Result<T, SqliteConnectionError>>) -> Command,
     {
         let (send, recv) = oneshot::channel();
-        let mut send_send = self.send.lock().await;
+        let mut send_send = self.write_send.lock().await;
         send_send
             .send(make_command(send))
             .await
             .map_err(|_| SqliteConnectionError::SQLitePanic)?;
         recv.await.map_err(|_| SqliteConnectionError::SQLitePanic)?
     }
+
+    /// Like `send_write_command`, but dispatches to the reader pool instead of the single writer
+    /// connection -- only ever used for `Command::Query` and `Command::ExportChangeset`. Cloning
+    /// the sender (rather than sharing a mutex over it, as `write_send` needs) is what lets
+    /// multiple reads be in flight to different readers at once.
+    async fn send_read_command<F, T>(&self, make_command: F) -> Result<T, SqliteConnectionError>
+    where
+        F: FnOnce(oneshot::Sender<Result<T, SqliteConnectionError>>) -> Command,
+    {
+        let (send, recv) = oneshot::channel();
+        self.read_send
+            .clone()
+            .send(make_command(send))
+            .await
+            .map_err(|_| SqliteConnectionError::SQLitePanic)?;
+        recv.await.map_err(|_| SqliteConnectionError::SQLitePanic)?
+    }
+
+    /// Takes a consistent, point-in-time snapshot of the database and blob store, writing it to
+    /// `dest` (which ends up with the same `g1.db` file and `blobs` directory layout as the
+    /// directory passed to `open`, so it can be opened directly as a fresh `SqliteConnection`).
+    ///
+    /// The database is copied with SQLite's online backup API, which runs through the command
+    /// thread (so it's serialized with other mutations) but copies the database page-by-page
+    /// rather than blocking writers for the whole duration -- `options` controls how many pages
+    /// are copied per step and how long to pause between steps, trading backup speed for how much
+    /// room concurrent writers get to run. `progress`, if given, is called after every step with
+    /// `(remaining, total)` pages left to copy; either way, each step also logs a debug line.
+    ///
+    /// The blob directory is copied (see `backup_blobs`) only after the database copy finishes, so
+    /// the snapshot is self-consistent: every blob a finished backup's database can reference is
+    /// already on disk under `dest`.
+    pub async fn backup(
+        &self,
+        dest: PathBuf,
+        options: BackupOptions,
+        progress: Option<BackupProgress>,
+    ) -> Result<(), SqliteConnectionError> {
+        create_dir_all(dest.join("blobs")).await?;
+
+        let mut db_dest = dest.clone();
+        db_dest.push("g1.db");
+        self.send_write_command(move |send| Command::Backup(db_dest, options, progress, send))
+            .await?;
+
+        self.backup_blobs(&dest.join("blobs")).await
+    }
+
+    /// Like `backup`, but streams just the database file (no blobs) to `writer`, via a temporary
+    /// file -- for shipping a snapshot somewhere that isn't a local path, e.g. over the network.
+    pub async fn backup_to_writer<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+        options: BackupOptions,
+        progress: Option<BackupProgress>,
+    ) -> Result<(), SqliteConnectionError> {
+        let mut tmp_path = self.path.clone();
+        tmp_path.push("tmp");
+        tmp_path.push(Uuid::new_v4().to_string());
+
+        self.send_write_command({
+            let tmp_path = tmp_path.clone();
+            move |send| Command::Backup(tmp_path, options, progress, send)
+        })
+        .await?;
+
+        let mut file = File::open(&tmp_path).await?;
+        let _ = tokio::io::copy(&mut file, &mut writer).await?;
+        tokio::fs::remove_file(&tmp_path).await?;
+        Ok(())
+    }
+
+    /// Hard-links (falling back to a copy, e.g. across filesystems) every blob file in this
+    /// connection's blob store into `dest`.
+    ///
+    /// This is safe to do outside the command thread: blob files are content-addressed and
+    /// `delete_blob` only ever removes the referencing database row, never the file itself, so
+    /// there's no file this could race with being written or removed.
+    async fn backup_blobs(&self, dest: &Path) -> Result<(), SqliteConnectionError> {
+        let mut src = self.path.clone();
+        src.push("blobs");
+
+        let mut entries = read_dir(&src).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let mut dest_path = dest.to_path_buf();
+            dest_path.push(entry.file_name());
+            if hard_link(entry.path(), &dest_path).await.is_err() {
+                let _ = copy(entry.path(), &dest_path).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reclaims blob storage `create_blob`/`delete_blob`/`delete_atom` couldn't clean up
+    /// themselves: files left on disk with no row referencing them any more (e.g. from a
+    /// `store_blob` that crashed before its matching `CreateBlob` landed), and rows referencing a
+    /// hash with no file on disk (e.g. removed out-of-band).
+    ///
+    /// Runs through the writer, so it's serialized with other mutations.
+    pub async fn gc_blobs(&self) -> Result<GcReport, SqliteConnectionError> {
+        self.send_write_command(|send| Command::GcBlobs(send)).await
+    }
+
+    /// Exports every mutation recorded since `since` (`0` to export the whole history), for
+    /// another store to replay with `apply_changeset` -- offline-first replication without either
+    /// side's callers going through anything beyond these two methods.
+    ///
+    /// The returned bytes embed the cursor a later call should pass as `since` to resume right
+    /// after this export; callers should persist it alongside whatever identifies the peer they
+    /// exported to. Blobs aren't included -- a receiver missing one of the hashes a replayed
+    /// `CreateBlob` references should separately `fetch_blob` it from the exporting side.
+    pub async fn export_changeset(&self, since: u64) -> Result<Bytes, SqliteConnectionError> {
+        self.send_read_command(move |send| Command::ExportChangeset(since, send))
+            .await
+    }
+
+    /// Applies a changeset produced by `export_changeset`, in order, as a single transaction.
+    ///
+    /// Each applied entry is itself appended to this store's own changelog, so a third store
+    /// syncing from here later picks it up too. `on_conflict`, if given, is called whenever an
+    /// entry conflicts with data already present (e.g. a `CreateName` whose `(ns, title)` was
+    /// created independently on both sides) to decide whether to skip it or abort the whole
+    /// changeset; with no resolver, any conflict aborts.
+    pub async fn apply_changeset(
+        &self,
+        changeset: Bytes,
+        on_conflict: Option<ConflictResolver>,
+    ) -> Result<ChangesetApplyReport, SqliteConnectionError> {
+        let (_, entries) = changeset::decode(&changeset)
+            .map_err(|err| SqliteConnectionError::InvalidQuery(err.to_string()))?;
+        self.send_write_command(move |send| Command::ApplyChangeset(entries, on_conflict, send))
+            .await
+    }
+
+    /// Watches the graph for mutations instead of polling, yielding each `ChangeEntry` admitted by
+    /// `filter` as it's committed.
+    ///
+    /// A batch of events is only delivered once the write that produced it actually commits --
+    /// mirroring SQLite's commit-hook semantics, nothing from a rolled-back transaction ever
+    /// reaches this stream. If it falls behind (see `tokio::sync::broadcast`'s docs on lagging),
+    /// it silently skips ahead to the oldest event still buffered rather than erroring out.
+    pub async fn subscribe(
+        &self,
+        filter: ChangeFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = ChangeEntry> + Send>>, SqliteConnectionError> {
+        let receiver = self
+            .send_write_command(move |send| Command::Subscribe(send))
+            .await?;
+        Ok(stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(entry) if filter.matches(&entry) => break Some((entry, (receiver, filter))),
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break None,
+                }
+            }
+        })
+        .boxed())
+    }
+
+    /// Exports the whole store -- atoms, names, edges, tags, blob metadata, and the blob payloads
+    /// themselves -- as a single portable SQLite file at `out_file`, for `import_portable` to
+    /// reconstitute elsewhere.
+    ///
+    /// The page database is copied with the same online backup API `backup` uses, then every blob
+    /// a `blobs` row references is read off disk and embedded into the export as a row of a
+    /// `blob_payloads (hash, data)` table keyed by `Hash`, so the file is self-contained -- nothing
+    /// under this store's `blobs` directory needs to travel alongside it.
+    pub async fn export_portable(&self, out_file: PathBuf) -> Result<(), SqliteConnectionError> {
+        self.send_write_command(move |send| Command::ExportPortable(out_file, send))
+            .await
+    }
+
+    /// The inverse of `export_portable`: reconstitutes a store at `db_dir` (which must not already
+    /// exist) from a portable file produced by it, returning a connection to the result.
+    ///
+    /// `in_file`'s schema becomes `db_dir`'s `g1.db` directly; each row of its `blob_payloads` table
+    /// is then streamed out into `db_dir`'s `blobs` directory under its claimed hash, re-hashing the
+    /// bytes first and refusing to import if they don't match (catching a corrupted or truncated
+    /// export), and skipping any blob whose file is already there with the right hash.
+    pub async fn import_portable(
+        db_dir: PathBuf,
+        in_file: PathBuf,
+    ) -> Result<SqliteConnection, SqliteConnectionError> {
+        create_dir_all(&db_dir).await?;
+        create_dir_all(db_dir.join("blobs")).await?;
+
+        let mut db_path = db_dir.clone();
+        db_path.push("g1.db");
+        let blobs_dir = db_dir.join("blobs");
+        spawn_blocking(move || -> Result<(), SqliteConnectionError> {
+            std::fs::copy(&in_file, &db_path)?;
+
+            let conn = rusqlite::Connection::open(&db_path)?;
+            let mut stmt = conn.prepare("select hash, data from blob_payloads")?;
+            let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+            while let Some(row) = rows.next()? {
+                let hash: String = row.get(0)?;
+                let data: Vec<u8> = row.get(1)?;
+
+                let mut hasher = Sha256::new();
+                hasher.input(&data);
+                let actual = Hash::from_bytes(hasher.result().as_slice()).to_string();
+                if actual != hash {
+                    return Err(SqliteConnectionError::InvalidQuery(format!(
+                        "blob_payloads row claims hash {} but its bytes hash to {}",
+                        hash, actual
+                    )));
+                }
+
+                let dest = blobs_dir.join(&hash);
+                if !dest.exists() {
+                    std::fs::write(dest, data)?;
+                }
+            }
+            drop(rows);
+            drop(stmt);
+            conn.execute_batch("drop table blob_payloads;")?;
+            Ok(())
+        })
+        .await
+        .map_err(tokio::io::Error::from)??;
+
+        SqliteConnection::open(db_dir).await
+    }
+
+    /// Bulk-loads `rows` (each already split into the columns `kind` expects -- see `ImportKind`)
+    /// as a single transaction, auto-creating an atom the first time each external-key column in
+    /// `rows` is seen so the same key always resolves to the same atom across the whole call.
+    ///
+    /// For throughput on large files, this reuses one prepared statement per row kind instead of
+    /// going through `Connection::batch`'s per-mutation dispatch.
+    pub async fn import_csv(
+        &self,
+        kind: ImportKind,
+        rows: Vec<Vec<String>>,
+    ) -> Result<ImportReport, SqliteConnectionError> {
+        self.send_write_command(move |send| Command::Import(kind, rows, send))
+            .await
+    }
+
+    /// Like `fetch_blob`, but only yields the `len` bytes starting at `offset`, seeking into the
+    /// content-addressed file instead of streaming it from the start -- for HTTP range requests,
+    /// resumable downloads, and media seeking without pulling a whole large blob through first.
+    pub async fn fetch_blob_range(
+        &self,
+        hash: Hash,
+        offset: u64,
+        len: u64,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, SqliteConnectionError>> + Send>>, SqliteConnectionError>
+    {
+        let mut path = self.path.clone();
+        path.push("blobs");
+        path.push(hash.to_string());
+
+        if let Some(cipher) = self.blob_cipher.clone() {
+            // An AEAD tag only verifies over the whole ciphertext, so there's no way to seek
+            // within an encrypted blob without decrypting it entirely first -- decrypt, then slice
+            // out the requested window.
+            let mut file = File::open(path).await?;
+            let mut contents = Vec::new();
+            let _ = file.read_to_end(&mut contents).await?;
+            if contents.len() < 12 {
+                return Err(SqliteConnectionError::Crypto(
+                    "encrypted blob is truncated".to_string(),
+                ));
+            }
+            let (nonce, ciphertext) = contents.split_at(12);
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| SqliteConnectionError::Crypto("failed to decrypt blob".to_string()))?;
+            let start = (offset as usize).min(plaintext.len());
+            let end = ((offset + len) as usize).min(plaintext.len());
+            return Ok(
+                stream::once(future::ready(Ok(Bytes::from(plaintext[start..end].to_vec())))).boxed(),
+            );
+        }
+
+        let mut file = File::open(path).await?;
+        let _ = file.seek(SeekFrom::Start(offset)).await?;
+        let mut remaining = len;
+        Ok(stream::poll_fn(move |cx| {
+            if remaining == 0 {
+                return std::task::Poll::Ready(None);
+            }
+            // `poll_read` only ever fills (at most) `buf`'s current length, not its capacity --
+            // a freshly-allocated `BytesMut` is zero-length, so leaving this at `new()` (as
+            // `fetch_blob`, below, still does) asks for a zero-byte read on every poll and the
+            // stream yields `Ok(0)` (i.e. EOF) immediately, no matter what's on disk.
+            let mut buf = BytesMut::with_capacity(8192);
+            buf.resize(8192, 0);
+            Pin::new(&mut file)
+                .poll_read(cx, &mut buf)
+                .map(|r| match r {
+                    Ok(0) => None,
+                    Ok(n) => {
+                        let n = (n as u64).min(remaining) as usize;
+                        remaining -= n as u64;
+                        Some(Ok(buf.freeze().slice(0..n)))
+                    }
+                    Err(e) => Some(Err(e.into())),
+                })
+        })
+        .boxed())
+    }
 }
 
 #[async_trait::async_trait]
@@ -160,12 +678,14 @@ impl Connection for SqliteConnection {
     type Error = SqliteConnectionError;
 
     async fn create_atom(&self) -> Result<Atom, Self::Error> {
-        self.send_command(|send| Command::CreateAtom(send)).await
+        self.send_write_command(|send| Command::CreateAtom(send)).await
     }
 
-    async fn delete_atom(&self, atom: Atom) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::DeleteAtom(atom, send))
-            .await
+    async fn delete_atom(&self, atom: Atom) -> Result<(), Self::Error> {
+        let _ = self
+            .send_write_command(move |send| Command::DeleteAtom(atom, send))
+            .await?;
+        Ok(())
     }
 
     async fn create_name(
@@ -174,25 +694,27 @@ impl Connection for SqliteConnection {
         ns: &str,
         title: &str,
         upsert: bool,
-    ) -> Result<bool, Self::Error> {
-        self.send_command(move |send| {
-            Command::CreateName(atom, ns.to_string(), title.to_string(), upsert, send)
-        })
-        .await
+    ) -> Result<(), Self::Error> {
+        let _ = self
+            .send_write_command(move |send| {
+                Command::CreateName(atom, ns.to_string(), title.to_string(), upsert, send)
+            })
+            .await?;
+        Ok(())
     }
 
     async fn delete_name(&self, ns: &str, title: &str) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::DeleteName(ns.to_string(), title.to_string(), send))
+        self.send_write_command(move |send| Command::DeleteName(ns.to_string(), title.to_string(), send))
             .await
     }
 
     async fn create_edge(&self, from: Atom, to: Atom, label: &str) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::CreateEdge(from, to, label.to_string(), send))
+        self.send_write_command(move |send| Command::CreateEdge(from, to, label.to_string(), send))
             .await
     }
 
     async fn delete_edge(&self, from: Atom, to: Atom, label: &str) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::DeleteEdge(from, to, label.to_string(), send))
+        self.send_write_command(move |send| Command::DeleteEdge(from, to, label.to_string(), send))
             .await
     }
 
@@ -200,17 +722,19 @@ impl Connection for SqliteConnection {
         &self,
         atom: Atom,
         key: &str,
-        value: &str,
+        value: TagValue,
         upsert: bool,
-    ) -> Result<bool, Self::Error> {
-        self.send_command(move |send| {
-            Command::CreateTag(atom, key.to_string(), value.to_string(), upsert, send)
-        })
-        .await
+    ) -> Result<(), Self::Error> {
+        let _ = self
+            .send_write_command(move |send| {
+                Command::CreateTag(atom, key.to_string(), value, upsert, send)
+            })
+            .await?;
+        Ok(())
     }
 
     async fn delete_tag(&self, atom: Atom, key: &str) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::DeleteTag(atom, key.to_string(), send))
+        self.send_write_command(move |send| Command::DeleteTag(atom, key.to_string(), send))
             .await
     }
 
@@ -221,15 +745,17 @@ impl Connection for SqliteConnection {
         mime: Mime,
         hash: Hash,
         upsert: bool,
-    ) -> Result<bool, Self::Error> {
-        self.send_command(move |send| {
-            Command::CreateBlob(atom, kind.to_string(), mime, hash, upsert, send)
-        })
-        .await
+    ) -> Result<(), Self::Error> {
+        let _ = self
+            .send_write_command(move |send| {
+                Command::CreateBlob(atom, kind.to_string(), mime, hash, upsert, send)
+            })
+            .await?;
+        Ok(())
     }
 
     async fn delete_blob(&self, atom: Atom, kind: &str, mime: Mime) -> Result<bool, Self::Error> {
-        self.send_command(move |send| Command::DeleteBlob(atom, kind.to_string(), mime, send))
+        self.send_write_command(move |send| Command::DeleteBlob(atom, kind.to_string(), mime, send))
             .await
     }
 
@@ -241,14 +767,66 @@ impl Connection for SqliteConnection {
         path.push("blobs");
         path.push(hash.to_string());
 
+        if let Some(cipher) = self.blob_cipher.clone() {
+            // An AEAD tag only verifies over the whole ciphertext, so there's no way to stream
+            // decryption incrementally -- read the (nonce-prefixed) ciphertext in, decrypt it in
+            // one shot, and hand the plaintext back as a single-item stream.
+            let mut file = File::open(path).await?;
+            let mut contents = Vec::new();
+            let _ = file.read_to_end(&mut contents).await?;
+            if contents.len() < 12 {
+                return Err(SqliteConnectionError::Crypto(
+                    "encrypted blob is truncated".to_string(),
+                ));
+            }
+            let (nonce, ciphertext) = contents.split_at(12);
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(nonce), ciphertext)
+                .map_err(|_| SqliteConnectionError::Crypto("failed to decrypt blob".to_string()))?;
+            let mut hasher = Sha256::new();
+            hasher.input(&plaintext);
+            let actual = Hash::from_bytes(hasher.result().as_slice());
+            if actual != hash {
+                return Err(SqliteConnectionError::Corrupt {
+                    expected: hash,
+                    actual,
+                });
+            }
+            return Ok(stream::once(future::ready(Ok(Bytes::from(plaintext)))).boxed());
+        }
+
         let mut file = File::open(path).await?;
+        let mut hasher = Some(Sha256::new());
         Ok(stream::poll_fn(move |cx| {
             let mut buf = BytesMut::new();
             Pin::new(&mut file)
                 .poll_read(cx, &mut buf)
                 .map(|r| match r {
-                    Ok(0) => None,
-                    Ok(_) => Some(Ok(buf.freeze())),
+                    Ok(0) => {
+                        let actual = Hash::from_bytes(
+                            hasher
+                                .take()
+                                .expect("a poll_fn stream is never polled again after None")
+                                .result()
+                                .as_slice(),
+                        );
+                        if actual == hash {
+                            None
+                        } else {
+                            Some(Err(SqliteConnectionError::Corrupt {
+                                expected: hash,
+                                actual,
+                            }))
+                        }
+                    }
+                    Ok(_) => {
+                        let chunk = buf.freeze();
+                        hasher
+                            .as_mut()
+                            .expect("a poll_fn stream is never polled again after None")
+                            .input(&chunk);
+                        Some(Ok(chunk))
+                    }
                     Err(e) => Some(Err(e.into())),
                 })
         })
@@ -265,14 +843,34 @@ impl Connection for SqliteConnection {
 
         let mut file = File::create(&tmp_path).await?;
         let mut hasher = Sha256::new();
+        // The content hash is always computed over the plaintext, so dedup/addressing behaves
+        // identically whether or not the store is encrypted; encryption, if any, is applied to
+        // the bytes actually written to disk, below.
+        let mut plaintext = self.blob_cipher.is_some().then(Vec::new);
         while let Some(r) = data.next().await {
             let chunk = r?;
             hasher.input(&chunk);
-            let _ = file.write(&chunk).await?;
+            match plaintext.as_mut() {
+                Some(buf) => buf.extend_from_slice(&chunk),
+                None => {
+                    let _ = file.write(&chunk).await?;
+                }
+            }
         }
-        file.sync_all().await?;
         let hash = Hash::from_bytes(hasher.result().as_slice());
 
+        if let Some(cipher) = &self.blob_cipher {
+            let plaintext = plaintext.expect("blob_cipher implies buffering the plaintext");
+            let mut nonce = [0u8; 12];
+            OsRng.fill_bytes(&mut nonce);
+            let ciphertext = cipher
+                .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+                .map_err(|_| SqliteConnectionError::Crypto("failed to encrypt blob".to_string()))?;
+            let _ = file.write(&nonce).await?;
+            let _ = file.write(&ciphertext).await?;
+        }
+        file.sync_all().await?;
+
         let mut path = self.path.clone();
         path.push("blobs");
         path.push(hash.to_string());
@@ -315,9 +913,21 @@ impl Connection for SqliteConnection {
         limit: Option<usize>,
         query: &NamelessQuery,
     ) -> Result<Vec<Vec<Arc<str>>>, Self::Error> {
-        self.send_command(move |send| Command::Query(limit, query.clone(), send))
+        self.send_read_command(move |send| Command::Query(limit, query.clone(), send))
+            .await
+    }
+
+    async fn batch(&self, mutations: Vec<Mutation>) -> Result<Vec<MutationResult>, Self::Error> {
+        self.send_write_command(move |send| Command::Batch(mutations, send))
             .await
     }
+
+    async fn subscribe(
+        &self,
+        filter: ChangeFilter,
+    ) -> Result<Pin<Box<dyn Stream<Item = ChangeEntry> + Send>>, Self::Error> {
+        SqliteConnection::subscribe(self, filter).await
+    }
 }
 
 /// An error performing an operation on an `SqliteConnection`.
@@ -340,10 +950,92 @@ pub enum SqliteConnectionError {
     /// The SQLite thread panicked.
     #[error("The SQLite thread panicked")]
     SQLitePanic,
+
+    /// A `batch` kept losing its optimistic-concurrency race even after retrying.
+    #[error("gave up on a batch after repeatedly losing a concurrency race")]
+    BatchConflict,
+
+    /// A mutation within a `batch` failed; `index` is its position in the `Vec<Mutation>` that was
+    /// passed in. Every mutation in the batch, including those before `index`, was rolled back --
+    /// `batch` is all-or-nothing.
+    #[error("mutation {index} in batch failed: {source}")]
+    BatchMutationFailed {
+        /// The index of the failing mutation within the batch.
+        index: usize,
+        /// The underlying SQLite error.
+        source: rusqlite::Error,
+    },
+
+    /// Encrypting or decrypting a blob failed, e.g. because of a wrong key or corrupted/truncated
+    /// ciphertext.
+    #[error("cryptography error: {0}")]
+    Crypto(String),
+
+    /// `fetch_blob` re-hashes a blob's bytes on the way out, and the result didn't match the hash
+    /// it was stored and requested under -- the stored content was corrupted (or the file was
+    /// swapped for a different one) after it was written.
+    #[error("blob {expected} is corrupt (its stored content actually hashes to {actual})")]
+    Corrupt {
+        /// The hash this blob was stored and requested under.
+        expected: Hash,
+        /// The hash the stored bytes actually hash to.
+        actual: Hash,
+    },
+}
+
+/// The `PRAGMA key = ...;` statement SQLCipher expects to unlock a database, given the raw key
+/// bytes passed to `open_encrypted`.
+fn key_pragma(key: &[u8]) -> String {
+    let hex_key = key.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    format!("PRAGMA key = \"x'{}'\";", hex_key)
+}
+
+/// Derives the 32-byte key used to encrypt/decrypt blobs from the master key passed to
+/// `open_encrypted`, so the blob key differs from the literal key handed to SQLCipher.
+fn derive_blob_key(key: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(b"g1-sqlite-connection blob key");
+    hasher.input(key);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(hasher.result().as_slice());
+    out
 }
 
 impl g1_common::Error for SqliteConnectionError {
     fn invalid_query(msg: String) -> SqliteConnectionError {
         SqliteConnectionError::InvalidQuery(msg)
     }
+
+    fn is_transient(&self) -> bool {
+        match self {
+            SqliteConnectionError::IO(err) => matches!(
+                err.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            ),
+            SqliteConnectionError::SQLite(err) | SqliteConnectionError::BatchMutationFailed {
+                source: err,
+                ..
+            } => matches!(
+                err,
+                rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error {
+                        code: rusqlite::ErrorCode::DatabaseBusy,
+                        ..
+                    }
+                        | rusqlite::ffi::Error {
+                            code: rusqlite::ErrorCode::DatabaseLocked,
+                            ..
+                        },
+                    _,
+                )
+            ),
+            SqliteConnectionError::InvalidQuery(_)
+            | SqliteConnectionError::SQLitePanic
+            | SqliteConnectionError::BatchConflict
+            | SqliteConnectionError::Crypto(_)
+            | SqliteConnectionError::Corrupt { .. } => false,
+        }
+    }
 }
@@ -1,20 +1,87 @@
-use crate::{Atom, Command, SqliteError};
-use g1_common::naive_solve::naive_solve;
+use crate::{
+    changeset::{self, ChangeEntry},
+    cmd::{ChangesetApplyReport, ConflictAction, GcReport, ImportKind, ImportReport},
+    compile::compile,
+    Atom, Command, SqliteError,
+};
+use g1_common::{Bytes, Hash, Mutation, MutationResult, TagValue};
 use log::error;
-use rusqlite::{Connection, NO_PARAMS};
-use std::sync::Arc;
-use tokio::sync::oneshot::Sender;
+use rusqlite::{params_from_iter, Connection, OptionalExtension, Transaction, NO_PARAMS};
+use std::{
+    collections::HashMap,
+    fs::{read_dir, remove_file},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    thread::sleep,
+    time::Duration,
+};
+use tokio::sync::{broadcast, oneshot::Sender};
+
+/// How many times a `Command::Batch` will restart after losing an optimistic-concurrency race
+/// (currently, only atom-ID collisions) before giving up.
+const BATCH_RETRIES: u32 = 3;
+
+/// How many times `with_busy_retry` will retry a "database is busy/locked" error before giving up
+/// and surfacing it, and the initial delay before the first retry -- doubled after every attempt.
+const BUSY_RETRIES: u32 = 5;
+const BUSY_INITIAL_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Retries `f` with exponential backoff as long as it keeps failing with SQLite's transient
+/// "database is busy" or "database is locked" errors (expected occasionally under WAL, e.g. while
+/// a reader's snapshot is being checkpointed out from under it), surfacing any other error, or the
+/// busy error itself once `BUSY_RETRIES` is exhausted, immediately.
+fn with_busy_retry<T>(mut f: impl FnMut() -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+    let mut backoff = BUSY_INITIAL_BACKOFF;
+    let mut retries = BUSY_RETRIES;
+    loop {
+        match f() {
+            Err(e) if is_busy(&e) && retries > 0 => {
+                retries -= 1;
+                sleep(backoff);
+                backoff *= 2;
+            }
+            result => break result,
+        }
+    }
+}
+
+/// Whether `err` is SQLite's transient "database is busy" or "database is locked" error, as
+/// opposed to a permanent failure.
+fn is_busy(err: &rusqlite::Error) -> bool {
+    matches!(
+        err,
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error {
+                code: rusqlite::ErrorCode::DatabaseBusy,
+                ..
+            }
+                | rusqlite::ffi::Error {
+                    code: rusqlite::ErrorCode::DatabaseLocked,
+                    ..
+                },
+            _,
+        )
+    )
+}
 
 impl Command {
-    pub(crate) fn run(self, conn: &mut Connection) {
+    pub(crate) fn run(self, conn: &mut Connection, blobs_dir: &Path, changes: &broadcast::Sender<ChangeEntry>) {
         match self {
             Command::CreateAtom(send) => {
-                with_sender(send, || {
+                with_sender(send, move || {
                     let mut retries = 3;
                     loop {
                         let atom = Atom::new();
-                        match conn.execute("insert into atoms values (?)", &[atom.to_string()]) {
-                            Ok(_) => break Ok(atom),
+                        let tx = conn.transaction()?;
+                        match tx.execute("insert into atoms values (?)", &[atom.to_string()]) {
+                            Ok(_) => {
+                                let entry = log_change(&tx, ChangeEntry::CreateAtom(atom))?;
+                                tx.commit()?;
+                                notify(changes, entry);
+                                break Ok(atom);
+                            }
                             Err(rusqlite::Error::SqliteFailure(
                                 rusqlite::ffi::Error {
                                     code: rusqlite::ErrorCode::ConstraintViolation,
@@ -33,209 +100,945 @@ impl Command {
             Command::DeleteAtom(atom, send) => with_sender(send, move || {
                 let tx = conn.transaction()?;
 
+                let hashes = tx
+                    .prepare("select distinct hash from blobs where atom = ?")?
+                    .query_map(&[atom.to_string()], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
                 let _ = tx.execute("delete from names where atom = ?", &[atom.to_string()])?;
                 let _ = tx.execute("delete from edges where edge_from = ?", &[atom.to_string()])?;
                 let _ = tx.execute("delete from edges where edge_to = ?", &[atom.to_string()])?;
                 let _ = tx.execute("delete from tags where atom = ?", &[atom.to_string()])?;
                 let _ = tx.execute("delete from blobs where atom = ?", &[atom.to_string()])?;
 
-                tx.finish()?;
+                let entry = log_change(&tx, ChangeEntry::DeleteAtom(atom))?;
+                tx.commit()?;
+                notify(changes, entry);
+
+                // Only reclaim a blob's file once its row deletion has actually committed, so a
+                // crash beforehand leaves the row and file both intact rather than a row with no
+                // backing file.
+                for hash in &hashes {
+                    gc_hash_if_unreferenced(conn, blobs_dir, hash)?;
+                }
+
                 Ok(())
             }),
             Command::CreateName(atom, ns, title, true, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
                         "insert or replace into names values (?, ?, ?)",
-                        &[atom.to_string(), ns, title],
+                        &[atom.to_string(), ns.clone(), title.clone()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateName { atom, ns, title })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::CreateName(atom, ns, title, false, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
                         "insert into names values (?, ?, ?)",
-                        &[atom.to_string(), ns, title],
+                        &[atom.to_string(), ns.clone(), title.clone()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateName { atom, ns, title })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::DeleteName(ns, title, send) => with_sender(send, move || {
-                conn.execute("delete from names where ns = ? and title = ?", &[ns, title])
-                    .map(|n| match n {
-                        0 => false,
-                        1 => true,
-                        n => {
-                            error!("unexpected result from deleting name: {}", n);
-                            true
-                        }
-                    })
-                    .map_err(From::from)
+                let tx = conn.transaction()?;
+                let existed = existed(
+                    tx.execute(
+                        "delete from names where ns = ? and title = ?",
+                        &[ns.clone(), title.clone()],
+                    )?,
+                    "name",
+                );
+                let entry = if existed {
+                    Some(log_change(&tx, ChangeEntry::DeleteName { ns, title })?)
+                } else {
+                    None
+                };
+                tx.commit()?;
+                if let Some(entry) = entry {
+                    notify(changes, entry);
+                }
+                Ok(existed)
             }),
             Command::CreateEdge(from, to, label, send) => {
                 with_sender(send, move || {
-                    match conn.execute(
+                    let tx = conn.transaction()?;
+                    match tx.execute(
                         "insert into edges values (?, ?, ?)",
-                        &[from.to_string(), to.to_string(), label],
+                        &[from.to_string(), to.to_string(), label.clone()],
                     ) {
-                        Ok(_) => Ok(false),
-                        Err(rusqlite::Error::SqliteFailure(
-                            rusqlite::ffi::Error {
-                                code: rusqlite::ErrorCode::ConstraintViolation,
-                                extended_code: 2067,
-                            },
-                            _,
-                        )) => Ok(true),
+                        Ok(_) => {
+                            let entry =
+                                log_change(&tx, ChangeEntry::CreateEdge { from, to, label })?;
+                            tx.commit()?;
+                            notify(changes, entry);
+                            Ok(false)
+                        }
+                        Err(e) if is_unique_violation(&e) => Ok(true),
                         Err(e) => Err(e.into()),
                     }
                 });
             }
             Command::DeleteEdge(from, to, label, send) => with_sender(send, move || {
-                conn.execute(
-                    "delete from edges where edge_from = ? and edge_to = ? and label = ?",
-                    &[from.to_string(), to.to_string(), label],
-                )
-                .map(|n| match n {
-                    0 => false,
-                    1 => true,
-                    n => {
-                        error!("unexpected result from deleting edge: {}", n);
-                        true
-                    }
-                })
-                .map_err(From::from)
+                let tx = conn.transaction()?;
+                let existed = existed(
+                    tx.execute(
+                        "delete from edges where edge_from = ? and edge_to = ? and label = ?",
+                        &[from.to_string(), to.to_string(), label.clone()],
+                    )?,
+                    "edge",
+                );
+                let entry = if existed {
+                    Some(log_change(&tx, ChangeEntry::DeleteEdge { from, to, label })?)
+                } else {
+                    None
+                };
+                tx.commit()?;
+                if let Some(entry) = entry {
+                    notify(changes, entry);
+                }
+                Ok(existed)
             }),
             Command::CreateTag(atom, key, value, true, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
-                        "insert or replace into tags values (?, ?, ?)",
-                        &[atom.to_string(), key, value],
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
+                        "insert or replace into tags values (?, ?, ?, ?)",
+                        &[atom.to_string(), key.clone(), value.encode(), value.kind().to_string()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateTag { atom, key, value })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::CreateTag(atom, key, value, false, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
-                        "insert into tags values (?, ?, ?)",
-                        &[atom.to_string(), key, value],
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
+                        "insert into tags values (?, ?, ?, ?)",
+                        &[atom.to_string(), key.clone(), value.encode(), value.kind().to_string()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateTag { atom, key, value })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::DeleteTag(atom, key, send) => with_sender(send, move || {
-                conn.execute(
-                    "delete from tags where atom = ? and key = ?",
-                    &[atom.to_string(), key],
-                )
-                .map(|n| match n {
-                    0 => false,
-                    1 => true,
-                    n => {
-                        error!("unexpected result from deleting tag: {}", n);
-                        true
-                    }
-                })
-                .map_err(From::from)
+                let tx = conn.transaction()?;
+                let existed = existed(
+                    tx.execute(
+                        "delete from tags where atom = ? and key = ?",
+                        &[atom.to_string(), key.clone()],
+                    )?,
+                    "tag",
+                );
+                let entry = if existed {
+                    Some(log_change(&tx, ChangeEntry::DeleteTag { atom, key })?)
+                } else {
+                    None
+                };
+                tx.commit()?;
+                if let Some(entry) = entry {
+                    notify(changes, entry);
+                }
+                Ok(existed)
             }),
             Command::CreateBlob(atom, kind, mime, hash, true, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
                         "insert or replace into blobs values (?, ?, ?, ?)",
-                        &[atom.to_string(), kind, mime.to_string(), hash.to_string()],
+                        &[atom.to_string(), kind.clone(), mime.to_string(), hash.to_string()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateBlob { atom, kind, mime, hash })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::CreateBlob(atom, kind, mime, hash, false, send) => {
                 with_sender(send, move || {
-                    let _ = conn.execute(
+                    let tx = conn.transaction()?;
+                    let _ = tx.execute(
                         "insert into blobs values (?, ?, ?, ?)",
-                        &[atom.to_string(), kind, mime.to_string(), hash.to_string()],
+                        &[atom.to_string(), kind.clone(), mime.to_string(), hash.to_string()],
                     )?;
+                    let entry = log_change(&tx, ChangeEntry::CreateBlob { atom, kind, mime, hash })?;
+                    tx.commit()?;
+                    notify(changes, entry);
                     Ok(())
                 });
             }
             Command::DeleteBlob(atom, kind, mime, send) => with_sender(send, move || {
-                conn.execute(
-                    "delete from blobs where atom = ? and kind = ? and mime = ?",
-                    &[atom.to_string(), kind, mime.to_string()],
-                )
-                .map(|n| match n {
-                    0 => false,
-                    1 => true,
-                    n => {
-                        error!("unexpected result from deleting blob: {}", n);
-                        true
-                    }
-                })
-                .map_err(From::from)
+                let tx = conn.transaction()?;
+
+                let hash: Option<String> = tx
+                    .query_row(
+                        "select hash from blobs where atom = ? and kind = ? and mime = ?",
+                        &[atom.to_string(), kind.clone(), mime.to_string()],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                let existed = existed(
+                    tx.execute(
+                        "delete from blobs where atom = ? and kind = ? and mime = ?",
+                        &[atom.to_string(), kind.clone(), mime.to_string()],
+                    )?,
+                    "blob",
+                );
+                let entry = if existed {
+                    Some(log_change(&tx, ChangeEntry::DeleteBlob { atom, kind, mime })?)
+                } else {
+                    None
+                };
+                tx.commit()?;
+                if let Some(entry) = entry {
+                    notify(changes, entry);
+                }
+
+                // Only reclaim the blob's file once the row deletion has actually committed, so a
+                // crash beforehand leaves the row and file both intact rather than a row with no
+                // backing file.
+                if let Some(hash) = hash {
+                    gc_hash_if_unreferenced(conn, blobs_dir, &hash)?;
+                }
+
+                Ok(existed)
             }),
             Command::Query(limit, query, send) => {
+                with_sender(send, move || {
+                    with_busy_retry(|| {
+                        let tx = conn.transaction()?;
+
+                        let (sql, params) = compile(&query, limit);
+                        let solns = tx
+                            .prepare(&sql)?
+                            .query_and_then(params_from_iter(params.iter()), |row| {
+                                (0..row.as_ref().column_count())
+                                    .map(|i| Ok(row.get::<_, String>(i)?))
+                                    .collect::<rusqlite::Result<Vec<String>>>()
+                            })?
+                            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                        tx.finish()?;
+
+                        Ok(solns)
+                    })
+                    .map(|solns: Vec<Vec<String>>| {
+                        solns
+                            .into_iter()
+                            .map(|row| row.into_iter().map(Arc::from).collect())
+                            .collect()
+                    })
+                    .map_err(From::from)
+                });
+            }
+            Command::Batch(mutations, send) => {
+                with_sender(send, move || {
+                    let mut retries = BATCH_RETRIES;
+                    loop {
+                        let tx = conn.transaction()?;
+                        match apply_batch(&tx, &mutations) {
+                            Ok(results) => {
+                                let mut entries = Vec::new();
+                                for (mutation, result) in mutations.iter().zip(&results) {
+                                    if let Some(entry) = mutation_to_change(mutation, result) {
+                                        entries.push(log_change(&tx, entry)?);
+                                    }
+                                }
+                                tx.commit()?;
+                                for entry in entries {
+                                    notify(changes, entry);
+                                }
+                                break Ok(results);
+                            }
+                            Err((i, e))
+                                if is_unique_violation(&e)
+                                    && matches!(mutations[i], Mutation::CreateAtom)
+                                    && retries > 0 =>
+                            {
+                                retries -= 1;
+                                error!("Batch lost an optimistic-concurrency race; retrying");
+                            }
+                            Err((i, e))
+                                if is_unique_violation(&e)
+                                    && matches!(mutations[i], Mutation::CreateAtom) =>
+                            {
+                                break Err(SqliteError::BatchConflict)
+                            }
+                            Err((index, source)) => {
+                                break Err(SqliteError::BatchMutationFailed { index, source })
+                            }
+                        }
+                    }
+                });
+            }
+            Command::Backup(dest, options, mut progress, send) => {
+                with_sender(send, move || {
+                    let mut dst = Connection::open(dest)?;
+                    let backup = rusqlite::backup::Backup::new(conn, &mut dst)?;
+                    loop {
+                        let result = backup.step(options.pages_per_step)?;
+                        let p = backup.progress();
+                        log::debug!("backup: {} of {} pages remaining", p.remaining, p.pagecount);
+                        if let Some(progress) = progress.as_mut() {
+                            progress(p.remaining, p.pagecount);
+                        }
+                        if result == rusqlite::backup::StepResult::Done {
+                            break Ok(());
+                        }
+                        if options.pause > Duration::from_secs(0) {
+                            sleep(options.pause);
+                        }
+                    }
+                });
+            }
+            Command::GcBlobs(send) => {
                 with_sender(send, move || {
                     let tx = conn.transaction()?;
 
-                    let atoms = tx
-                        .prepare("select atom from atoms")?
-                        .query_and_then(NO_PARAMS, |row| Ok(Arc::from(row.get::<_, String>(0)?)))?
-                        .collect::<Result<Vec<_>, SqliteError>>()?;
+                    let mut reclaimed = Vec::new();
+                    for entry in read_dir(blobs_dir)? {
+                        let entry = entry?;
+                        let hash = entry.file_name().to_string_lossy().into_owned();
+                        let refs: i64 = tx.query_row(
+                            "select count(*) from blobs where hash = ?",
+                            &[&hash],
+                            |row| row.get(0),
+                        )?;
+                        if refs == 0 {
+                            let size = entry.metadata()?.len();
+                            remove_file(entry.path())?;
+                            if let Ok(hash) = Hash::from_str(&hash) {
+                                reclaimed.push((hash, size));
+                            }
+                        }
+                    }
 
-                    let names = tx
-                        .prepare("select atom, ns, title from names")?
-                        .query_and_then(NO_PARAMS, |row| {
-                            Ok((
-                                Arc::from(row.get::<_, String>(0)?),
-                                Arc::from(row.get::<_, String>(1)?),
-                                Arc::from(row.get::<_, String>(2)?),
-                            ))
+                    let dangling = tx
+                        .prepare("select rowid, hash from blobs")?
+                        .query_map(NO_PARAMS, |row| {
+                            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
                         })?
-                        .collect::<Result<Vec<_>, SqliteError>>()?;
+                        .collect::<rusqlite::Result<Vec<_>>>()?
+                        .into_iter()
+                        .filter(|(_, hash)| !blobs_dir.join(hash).exists())
+                        .collect::<Vec<_>>();
+                    let removed_rows = dangling.len();
+                    for (rowid, _) in dangling {
+                        let _ = tx.execute("delete from blobs where rowid = ?", &[rowid])?;
+                    }
 
-                    let edges = tx
-                        .prepare("select edge_from, edge_to, label from edges")?
-                        .query_and_then(NO_PARAMS, |row| {
-                            Ok((
-                                Arc::from(row.get::<_, String>(0)?),
-                                Arc::from(row.get::<_, String>(1)?),
-                                Arc::from(row.get::<_, String>(2)?),
-                            ))
+                    tx.commit()?;
+                    Ok(GcReport {
+                        reclaimed,
+                        removed_rows,
+                    })
+                });
+            }
+            Command::ExportChangeset(since, send) => {
+                with_sender(send, move || {
+                    let mut cursor = since;
+                    let entries = conn
+                        .prepare("select seq, entry from changelog where seq > ? order by seq")?
+                        .query_map(&[since as i64], |row| {
+                            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
                         })?
+                        .collect::<rusqlite::Result<Vec<_>>>()?
+                        .into_iter()
+                        .map(|(seq, entry)| {
+                            cursor = cursor.max(seq as u64);
+                            serde_json::from_str::<ChangeEntry>(&entry)
+                                .map_err(|e| SqliteError::InvalidQuery(e.to_string()))
+                        })
                         .collect::<Result<Vec<_>, SqliteError>>()?;
 
-                    let tags = tx
-                        .prepare("select atom, key, value from tags")?
-                        .query_and_then(NO_PARAMS, |row| {
-                            Ok((
-                                Arc::from(row.get::<_, String>(0)?),
-                                Arc::from(row.get::<_, String>(1)?),
-                                Arc::from(row.get::<_, String>(2)?),
-                            ))
-                        })?
-                        .collect::<Result<Vec<_>, SqliteError>>()?;
+                    Ok(Bytes::from(changeset::encode(cursor, entries)))
+                });
+            }
+            Command::ApplyChangeset(entries, mut on_conflict, send) => {
+                with_sender(send, move || {
+                    let tx = conn.transaction()?;
+                    let mut applied_entries = Vec::new();
+                    let mut skipped = 0;
+                    for entry in &entries {
+                        match apply_change_entry(&tx, entry) {
+                            Ok(()) => {
+                                applied_entries.push(log_change(&tx, entry.clone())?);
+                            }
+                            Err(e) if is_unique_violation(&e) => {
+                                let action = match on_conflict.as_mut() {
+                                    Some(resolve) => resolve(entry, &e),
+                                    None => ConflictAction::Abort,
+                                };
+                                match action {
+                                    ConflictAction::Skip => skipped += 1,
+                                    ConflictAction::Abort => return Err(e.into()),
+                                }
+                            }
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                    tx.commit()?;
+                    let applied = applied_entries.len();
+                    for entry in applied_entries {
+                        notify(changes, entry);
+                    }
+                    Ok(ChangesetApplyReport { applied, skipped })
+                });
+            }
+            Command::Subscribe(send) => {
+                // Every subscriber shares this one broadcast channel; `SqliteConnection::subscribe`
+                // applies its caller's `ChangeFilter` client-side as it adapts the raw receiver
+                // into a `Stream`.
+                let _ = send.send(Ok(changes.subscribe()));
+            }
+            Command::ExportPortable(out_file, send) => {
+                with_sender(send, move || {
+                    let mut dst = Connection::open(&out_file)?;
+                    {
+                        let backup = rusqlite::backup::Backup::new(conn, &mut dst)?;
+                        while backup.step(100)? != rusqlite::backup::StepResult::Done {}
+                    }
 
-                    let blobs = tx
-                        .prepare("select atom, kind, mime, hash from blobs")?
-                        .query_and_then(NO_PARAMS, |row| {
-                            Ok((
-                                Arc::from(row.get::<_, String>(0)?),
-                                Arc::from(row.get::<_, String>(1)?),
-                                Arc::from(row.get::<_, String>(2)?),
-                                Arc::from(row.get::<_, String>(3)?),
-                            ))
-                        })?
-                        .collect::<Result<Vec<_>, SqliteError>>()?;
+                    // The backed-up database only has rows in `blobs` pointing at content-addressed
+                    // files under `blobs_dir` -- embed the bytes themselves so the export is a
+                    // single, self-contained file.
+                    dst.execute_batch(
+                        "create table blob_payloads \
+                         ( hash text not null primary key \
+                         , data blob not null \
+                         );",
+                    )?;
+                    let hashes = conn
+                        .prepare("select distinct hash from blobs")?
+                        .query_map(NO_PARAMS, |row| row.get::<_, String>(0))?
+                        .collect::<rusqlite::Result<Vec<_>>>()?;
+                    for hash in hashes {
+                        let data = std::fs::read(blobs_dir.join(&hash))?;
+                        dst.execute(
+                            "insert into blob_payloads (hash, data) values (?, ?)",
+                            rusqlite::params![hash, data],
+                        )?;
+                    }
+
+                    Ok(())
+                });
+            }
+            Command::Import(kind, rows, send) => {
+                with_sender(send, move || {
+                    for row in &rows {
+                        if row.len() != kind.columns() {
+                            return Err(SqliteError::InvalidQuery(format!(
+                                "expected {} column(s) for a {:?} row, got {}",
+                                kind.columns(),
+                                kind,
+                                row.len()
+                            )));
+                        }
+                    }
 
-                    tx.finish()?;
+                    let tx = conn.transaction()?;
+                    let mut pool: HashMap<String, Atom> = HashMap::new();
+                    let mut report = ImportReport::default();
+                    let mut entries = Vec::new();
 
-                    Ok(naive_solve(
-                        &atoms, &names, &edges, &tags, &blobs, limit, &query,
-                    ))
+                    for row in rows {
+                        match kind {
+                            ImportKind::Atom => {
+                                let (atom, created) = intern_atom(&tx, &mut pool, &row[0])?;
+                                if created {
+                                    entries.push(log_change(&tx, ChangeEntry::CreateAtom(atom))?);
+                                    report.inserted += 1;
+                                } else {
+                                    report.skipped += 1;
+                                }
+                            }
+                            ImportKind::Name => {
+                                let (atom, atom_created) = intern_atom(&tx, &mut pool, &row[0])?;
+                                if atom_created {
+                                    entries.push(log_change(&tx, ChangeEntry::CreateAtom(atom))?);
+                                    report.inserted += 1;
+                                }
+                                let (ns, title) = (row[1].clone(), row[2].clone());
+                                let n = tx.execute(
+                                    "insert or ignore into names values (?, ?, ?)",
+                                    &[atom.to_string(), ns.clone(), title.clone()],
+                                )?;
+                                if n > 0 {
+                                    entries.push(log_change(
+                                        &tx,
+                                        ChangeEntry::CreateName { atom, ns, title },
+                                    )?);
+                                    report.inserted += 1;
+                                } else {
+                                    report.skipped += 1;
+                                }
+                            }
+                            ImportKind::Edge => {
+                                let (from, from_created) = intern_atom(&tx, &mut pool, &row[0])?;
+                                if from_created {
+                                    entries.push(log_change(&tx, ChangeEntry::CreateAtom(from))?);
+                                    report.inserted += 1;
+                                }
+                                let (to, to_created) = intern_atom(&tx, &mut pool, &row[1])?;
+                                if to_created {
+                                    entries.push(log_change(&tx, ChangeEntry::CreateAtom(to))?);
+                                    report.inserted += 1;
+                                }
+                                let label = row[2].clone();
+                                let n = tx.execute(
+                                    "insert or ignore into edges values (?, ?, ?)",
+                                    &[from.to_string(), to.to_string(), label.clone()],
+                                )?;
+                                if n > 0 {
+                                    entries.push(log_change(
+                                        &tx,
+                                        ChangeEntry::CreateEdge { from, to, label },
+                                    )?);
+                                    report.inserted += 1;
+                                } else {
+                                    report.skipped += 1;
+                                }
+                            }
+                            ImportKind::Tag => {
+                                let (atom, atom_created) = intern_atom(&tx, &mut pool, &row[0])?;
+                                if atom_created {
+                                    entries.push(log_change(&tx, ChangeEntry::CreateAtom(atom))?);
+                                    report.inserted += 1;
+                                }
+                                let key = row[1].clone();
+                                let value = TagValue::Str(row[2].clone());
+                                let n = tx.execute(
+                                    "insert or ignore into tags values (?, ?, ?, ?)",
+                                    &[
+                                        atom.to_string(),
+                                        key.clone(),
+                                        value.encode(),
+                                        value.kind().to_string(),
+                                    ],
+                                )?;
+                                if n > 0 {
+                                    entries.push(log_change(
+                                        &tx,
+                                        ChangeEntry::CreateTag { atom, key, value },
+                                    )?);
+                                    report.inserted += 1;
+                                } else {
+                                    report.skipped += 1;
+                                }
+                            }
+                        }
+                    }
+
+                    tx.commit()?;
+                    for entry in entries {
+                        notify(changes, entry);
+                    }
+                    Ok(report)
                 });
             }
         }
     }
 }
 
+/// Resolves `key` to the atom `pool` has already interned it to, or creates a fresh one and
+/// interns it, for `Command::Import` -- so every row of a bulk import referencing the same
+/// external key (e.g. the same `from` id across many edge rows) resolves to one atom. Returns
+/// whether the atom was newly created, for the caller to fold into its `ImportReport`.
+fn intern_atom(
+    tx: &Transaction,
+    pool: &mut HashMap<String, Atom>,
+    key: &str,
+) -> rusqlite::Result<(Atom, bool)> {
+    if let Some(atom) = pool.get(key) {
+        return Ok((*atom, false));
+    }
+    let atom = Atom::new();
+    let _ = tx.execute("insert into atoms values (?)", &[atom.to_string()])?;
+    pool.insert(key.to_string(), atom);
+    Ok((atom, true))
+}
+
+/// Removes `blobs_dir`'s file for `hash` if no row in the `blobs` table references it any more.
+///
+/// Only called right after a mutation that could have dropped the last reference (`DeleteBlob`,
+/// `DeleteAtom`) has already committed, so a crash beforehand just leaves an orphaned file for
+/// `gc_blobs` to sweep later, rather than a row with no backing file.
+fn gc_hash_if_unreferenced(conn: &Connection, blobs_dir: &Path, hash: &str) -> Result<(), SqliteError> {
+    let refs: i64 = conn.query_row(
+        "select count(*) from blobs where hash = ?",
+        &[hash],
+        |row| row.get(0),
+    )?;
+    if refs == 0 {
+        if let Err(err) = remove_file(blobs_dir.join(hash)) {
+            if err.kind() != ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies every `Mutation` in order, within the given transaction. Bails out (without
+/// committing) at the first error, tagged with that mutation's index in `mutations` so the caller
+/// can report which one failed.
+fn apply_batch(
+    tx: &Transaction,
+    mutations: &[Mutation],
+) -> Result<Vec<MutationResult>, (usize, rusqlite::Error)> {
+    mutations
+        .iter()
+        .enumerate()
+        .map(|(i, m)| apply_mutation(tx, m).map_err(|e| (i, e)))
+        .collect()
+}
+
+fn apply_mutation(tx: &Transaction, mutation: &Mutation) -> rusqlite::Result<MutationResult> {
+    Ok(match mutation {
+        Mutation::CreateAtom => {
+            let atom = Atom::new();
+            let _ = tx.execute("insert into atoms values (?)", &[atom.to_string()])?;
+            MutationResult::CreateAtom(atom)
+        }
+        Mutation::DeleteAtom(atom) => {
+            // Unlike `DeleteName`/`DeleteEdge`/`DeleteTag`, this reports no "existed" flag:
+            // `Connection::delete_atom` is documented as cascading over whatever names/edges/tags/
+            // blobs currently reference `atom` -- there's no `atoms` table row to check for
+            // existence against (atoms are never actually deleted, see that doc comment), so
+            // deleting an atom that was never created, or one that's already had this done to it,
+            // is intentionally a no-op rather than a question with a meaningful answer.
+            let _ = tx.execute("delete from names where atom = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from edges where edge_from = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from edges where edge_to = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from tags where atom = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from blobs where atom = ?", &[atom.to_string()])?;
+            MutationResult::DeleteAtom
+        }
+        Mutation::CreateName {
+            atom,
+            ns,
+            title,
+            upsert: true,
+        } => {
+            let _ = tx.execute(
+                "insert or replace into names values (?, ?, ?)",
+                &[atom.to_string(), ns.clone(), title.clone()],
+            )?;
+            MutationResult::CreateName
+        }
+        Mutation::CreateName {
+            atom,
+            ns,
+            title,
+            upsert: false,
+        } => {
+            let _ = tx.execute(
+                "insert into names values (?, ?, ?)",
+                &[atom.to_string(), ns.clone(), title.clone()],
+            )?;
+            MutationResult::CreateName
+        }
+        Mutation::DeleteName { ns, title } => {
+            let n = tx.execute(
+                "delete from names where ns = ? and title = ?",
+                &[ns.clone(), title.clone()],
+            )?;
+            MutationResult::DeleteName(existed(n, "name"))
+        }
+        Mutation::CreateEdge { from, to, label } => {
+            match tx.execute(
+                "insert into edges values (?, ?, ?)",
+                &[from.to_string(), to.to_string(), label.clone()],
+            ) {
+                Ok(_) => MutationResult::CreateEdge(false),
+                Err(e) if is_unique_violation(&e) => MutationResult::CreateEdge(true),
+                Err(e) => return Err(e),
+            }
+        }
+        Mutation::DeleteEdge { from, to, label } => {
+            let n = tx.execute(
+                "delete from edges where edge_from = ? and edge_to = ? and label = ?",
+                &[from.to_string(), to.to_string(), label.clone()],
+            )?;
+            MutationResult::DeleteEdge(existed(n, "edge"))
+        }
+        Mutation::CreateTag {
+            atom,
+            key,
+            value,
+            upsert: true,
+        } => {
+            let _ = tx.execute(
+                "insert or replace into tags values (?, ?, ?, ?)",
+                &[atom.to_string(), key.clone(), value.encode(), value.kind().to_string()],
+            )?;
+            MutationResult::CreateTag
+        }
+        Mutation::CreateTag {
+            atom,
+            key,
+            value,
+            upsert: false,
+        } => {
+            let _ = tx.execute(
+                "insert into tags values (?, ?, ?, ?)",
+                &[atom.to_string(), key.clone(), value.encode(), value.kind().to_string()],
+            )?;
+            MutationResult::CreateTag
+        }
+        Mutation::DeleteTag { atom, key } => {
+            let n = tx.execute(
+                "delete from tags where atom = ? and key = ?",
+                &[atom.to_string(), key.clone()],
+            )?;
+            MutationResult::DeleteTag(existed(n, "tag"))
+        }
+        Mutation::CreateBlob {
+            atom,
+            kind,
+            mime,
+            hash,
+            upsert: true,
+        } => {
+            let _ = tx.execute(
+                "insert or replace into blobs values (?, ?, ?, ?)",
+                &[atom.to_string(), kind.clone(), mime.to_string(), hash.to_string()],
+            )?;
+            MutationResult::CreateBlob(false)
+        }
+        Mutation::CreateBlob {
+            atom,
+            kind,
+            mime,
+            hash,
+            upsert: false,
+        } => match tx.execute(
+            "insert into blobs values (?, ?, ?, ?)",
+            &[atom.to_string(), kind.clone(), mime.to_string(), hash.to_string()],
+        ) {
+            Ok(_) => MutationResult::CreateBlob(false),
+            Err(e) if is_unique_violation(&e) => MutationResult::CreateBlob(true),
+            Err(e) => return Err(e),
+        },
+        Mutation::DeleteBlob { atom, kind, mime } => {
+            // Unlike the single-op `Command::DeleteBlob`, this doesn't reclaim the blob's file on
+            // disk -- that needs `conn`, which is borrowed by the surrounding transaction for the
+            // whole batch. The now-possibly-unreferenced file is left for a later `GcBlobs` sweep.
+            let n = tx.execute(
+                "delete from blobs where atom = ? and kind = ? and mime = ?",
+                &[atom.to_string(), kind.clone(), mime.to_string()],
+            )?;
+            MutationResult::DeleteBlob(existed(n, "blob"))
+        }
+    })
+}
+
+/// Appends `entry` to `changelog`, for a later `export_changeset` to pick up.
+fn log_change(tx: &Transaction, entry: ChangeEntry) -> rusqlite::Result<ChangeEntry> {
+    let json = serde_json::to_string(&entry).expect("ChangeEntry always serializes");
+    let _ = tx.execute("insert into changelog (entry) values (?)", &[json])?;
+    Ok(entry)
+}
+
+/// Broadcasts `entry` to every live `subscribe` stream, once the transaction that `log_change`d it
+/// has actually committed -- callers only reach this after a successful `tx.commit()`, so a
+/// transaction that rolls back (by returning early on `?` instead) never reaches here, mirroring
+/// SQLite's commit-hook semantics. Ignoring the result is deliberate: `send` only errs when nobody
+/// is currently subscribed, which isn't a failure.
+fn notify(changes: &broadcast::Sender<ChangeEntry>, entry: ChangeEntry) {
+    let _ = changes.send(entry);
+}
+
+/// The `ChangeEntry` a `Mutation` applied by `apply_batch`/`apply_mutation` corresponds to, or
+/// `None` if it's a delete that turned out to be a no-op (nothing for a peer to replay).
+fn mutation_to_change(mutation: &Mutation, result: &MutationResult) -> Option<ChangeEntry> {
+    Some(match (mutation, result) {
+        (Mutation::CreateAtom, MutationResult::CreateAtom(atom)) => ChangeEntry::CreateAtom(*atom),
+        (Mutation::DeleteAtom(atom), MutationResult::DeleteAtom) => ChangeEntry::DeleteAtom(*atom),
+        (Mutation::CreateName { atom, ns, title, .. }, MutationResult::CreateName) => {
+            ChangeEntry::CreateName {
+                atom: *atom,
+                ns: ns.clone(),
+                title: title.clone(),
+            }
+        }
+        (Mutation::DeleteName { ns, title }, MutationResult::DeleteName(true)) => {
+            ChangeEntry::DeleteName {
+                ns: ns.clone(),
+                title: title.clone(),
+            }
+        }
+        (Mutation::DeleteName { .. }, MutationResult::DeleteName(false)) => return None,
+        (Mutation::CreateEdge { from, to, label }, MutationResult::CreateEdge(_)) => {
+            ChangeEntry::CreateEdge {
+                from: *from,
+                to: *to,
+                label: label.clone(),
+            }
+        }
+        (Mutation::DeleteEdge { from, to, label }, MutationResult::DeleteEdge(true)) => {
+            ChangeEntry::DeleteEdge {
+                from: *from,
+                to: *to,
+                label: label.clone(),
+            }
+        }
+        (Mutation::DeleteEdge { .. }, MutationResult::DeleteEdge(false)) => return None,
+        (Mutation::CreateTag { atom, key, value, .. }, MutationResult::CreateTag) => {
+            ChangeEntry::CreateTag {
+                atom: *atom,
+                key: key.clone(),
+                value: value.clone(),
+            }
+        }
+        (Mutation::DeleteTag { atom, key }, MutationResult::DeleteTag(true)) => {
+            ChangeEntry::DeleteTag {
+                atom: *atom,
+                key: key.clone(),
+            }
+        }
+        (Mutation::DeleteTag { .. }, MutationResult::DeleteTag(false)) => return None,
+        (Mutation::CreateBlob { atom, kind, mime, hash, .. }, MutationResult::CreateBlob(_)) => {
+            ChangeEntry::CreateBlob {
+                atom: *atom,
+                kind: kind.clone(),
+                mime: mime.clone(),
+                hash: *hash,
+            }
+        }
+        (Mutation::DeleteBlob { atom, kind, mime }, MutationResult::DeleteBlob(true)) => {
+            ChangeEntry::DeleteBlob {
+                atom: *atom,
+                kind: kind.clone(),
+                mime: mime.clone(),
+            }
+        }
+        (Mutation::DeleteBlob { .. }, MutationResult::DeleteBlob(false)) => return None,
+        (mutation, result) => unreachable!(
+            "apply_mutation's result {:?} doesn't match its mutation {:?}",
+            result, mutation
+        ),
+    })
+}
+
+/// Applies one entry from a changeset, the same way it was originally applied on the exporting
+/// side. Unlike `apply_mutation`, `CreateAtom` inserts the atom the entry says was created,
+/// rather than generating a fresh one -- a replayed changeset must reproduce the exact same data.
+fn apply_change_entry(tx: &Transaction, entry: &ChangeEntry) -> rusqlite::Result<()> {
+    match entry {
+        ChangeEntry::CreateAtom(atom) => {
+            let _ = tx.execute("insert into atoms values (?)", &[atom.to_string()])?;
+        }
+        ChangeEntry::DeleteAtom(atom) => {
+            let _ = tx.execute("delete from names where atom = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from edges where edge_from = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from edges where edge_to = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from tags where atom = ?", &[atom.to_string()])?;
+            let _ = tx.execute("delete from blobs where atom = ?", &[atom.to_string()])?;
+        }
+        ChangeEntry::CreateName { atom, ns, title } => {
+            let _ = tx.execute(
+                "insert into names values (?, ?, ?)",
+                &[atom.to_string(), ns.clone(), title.clone()],
+            )?;
+        }
+        ChangeEntry::DeleteName { ns, title } => {
+            let _ = tx.execute(
+                "delete from names where ns = ? and title = ?",
+                &[ns.clone(), title.clone()],
+            )?;
+        }
+        ChangeEntry::CreateEdge { from, to, label } => {
+            let _ = tx.execute(
+                "insert into edges values (?, ?, ?)",
+                &[from.to_string(), to.to_string(), label.clone()],
+            )?;
+        }
+        ChangeEntry::DeleteEdge { from, to, label } => {
+            let _ = tx.execute(
+                "delete from edges where edge_from = ? and edge_to = ? and label = ?",
+                &[from.to_string(), to.to_string(), label.clone()],
+            )?;
+        }
+        ChangeEntry::CreateTag { atom, key, value } => {
+            let _ = tx.execute(
+                "insert into tags values (?, ?, ?, ?)",
+                &[atom.to_string(), key.clone(), value.encode(), value.kind().to_string()],
+            )?;
+        }
+        ChangeEntry::DeleteTag { atom, key } => {
+            let _ = tx.execute(
+                "delete from tags where atom = ? and key = ?",
+                &[atom.to_string(), key.clone()],
+            )?;
+        }
+        ChangeEntry::CreateBlob {
+            atom,
+            kind,
+            mime,
+            hash,
+        } => {
+            let _ = tx.execute(
+                "insert into blobs values (?, ?, ?, ?)",
+                &[
+                    atom.to_string(),
+                    kind.clone(),
+                    mime.to_string(),
+                    hash.to_string(),
+                ],
+            )?;
+        }
+        ChangeEntry::DeleteBlob { atom, kind, mime } => {
+            let _ = tx.execute(
+                "delete from blobs where atom = ? and kind = ? and mime = ?",
+                &[atom.to_string(), kind.clone(), mime.to_string()],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn existed(rows_changed: usize, what: &str) -> bool {
+    match rows_changed {
+        0 => false,
+        1 => true,
+        n => {
+            error!("unexpected result from deleting {}: {}", what, n);
+            true
+        }
+    }
+}
+
+/// Whether `err` is the unique-constraint violation this module uses to detect an atom-ID
+/// collision (the same check `Command::CreateAtom` already does on its own).
+fn is_unique_violation(err: &rusqlite::Error) -> bool {
+    matches!(
+        err,
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error {
+                code: rusqlite::ErrorCode::ConstraintViolation,
+                extended_code: 2067,
+            },
+            _,
+        )
+    )
+}
+
 fn with_sender<F, T>(send: Sender<Result<T, SqliteError>>, func: F)
 where
     F: FnOnce() -> Result<T, SqliteError>,
@@ -1,17 +1,293 @@
+//! The `g1d` worker-thread database: a `Database` handle owning a dedicated SQLite connection
+//! thread, an append-only temporal schema (`tx_added`/`tx_retracted` plus a `transactions` table)
+//! supporting as-of/history reads, a per-predicate LRU result cache, and an observer pub/sub layer
+//! that broadcasts each committed mutation's `TxReport` to subscribers whose `ObserverFilter`
+//! matches.
+
 use anyhow::Result;
+use lru::LruCache;
 use mime::Mime;
 use rusqlite::{params, Connection, OptionalExtension};
 use std::{
+    any::Any,
     collections::HashSet,
     path::Path,
     sync::{mpsc, Arc},
     thread::{spawn, JoinHandle},
 };
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc as async_mpsc, oneshot};
 use uuid::Uuid;
 
+/// What kind of change a mutating query made to a row.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// A row was inserted.
+    Inserted,
+    /// A row was updated in place (e.g. an upsert replacing an existing row).
+    Updated,
+    /// A row was deleted.
+    Deleted,
+}
+
+/// A report of the rows a mutating query touched, built by the worker thread after it applies
+/// the query and pushed to every `Observer` whose `ObserverFilter` matches.
+#[derive(Clone, Debug)]
+pub struct TxReport {
+    /// The table the mutation touched (`"names"`, `"edges"`, `"tags"`, or `"blobs"`).
+    pub table: &'static str,
+    /// The coarse filter value for the rows that changed: a namespace for `names`, a key for
+    /// `edges`, or a kind for `tags`.
+    pub filter: String,
+    /// The atoms affected by the mutation.
+    pub atoms: Vec<String>,
+    /// What kind of change this was.
+    pub change: Change,
+}
+
+/// Registers interest in `TxReport`s matching a table and a coarse filter value, as passed to
+/// `Database::observe`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ObserverFilter {
+    /// Matches changes to `names` in the given namespace.
+    Names(String),
+    /// Matches changes to `edges` with the given key.
+    Edges(String),
+    /// Matches changes to `tags` of the given kind.
+    Tags(String),
+}
+
+impl ObserverFilter {
+    fn matches(&self, report: &TxReport) -> bool {
+        match self {
+            ObserverFilter::Names(ns) => report.table == "names" && *ns == report.filter,
+            ObserverFilter::Edges(key) => report.table == "edges" && *key == report.filter,
+            ObserverFilter::Tags(kind) => report.table == "tags" && *kind == report.filter,
+        }
+    }
+}
+
+/// A subscriber registered through `Database::observe`, held by the worker thread alongside the
+/// `Connection`.
+struct Observer {
+    filter: ObserverFilter,
+    send: async_mpsc::Sender<TxReport>,
+}
+
+/// Pushes `report` to every observer whose filter matches it, dropping observers whose receiver
+/// has gone away.
+fn notify(observers: &mut Vec<Observer>, report: &TxReport) {
+    observers.retain(|observer| {
+        if !observer.filter.matches(report) {
+            return true;
+        }
+        observer.send.try_send(report.clone()).is_ok()
+    });
+}
+
+/// A message sent to the worker thread: a `Query` to run against the `Connection`, a new
+/// `Observer` to register, or one of the cache-clearing, as-of-resolving, and history requests
+/// that `Database`'s own methods send.
+enum WorkerMsg {
+    Query(Query),
+    Observe(ObserverFilter, async_mpsc::Sender<TxReport>),
+    ClearCache(oneshot::Sender<()>),
+    ResolveAsOf(AsOf, oneshot::Sender<Result<i64>>),
+    History(String, oneshot::Sender<Result<Vec<HistoryEntry>>>),
+}
+
+/// A point to evaluate a read at, for reproducible snapshot queries: either a specific
+/// transaction, or the latest transaction committed at or before a Unix timestamp (seconds).
+///
+/// `Database::query_as_of` resolves either form down to the `tx_id` a read handler should filter
+/// against: `tx_added <= tx_id AND (tx_retracted IS NULL OR tx_retracted > tx_id)`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AsOf {
+    /// As of a specific transaction, inclusive.
+    Tx(i64),
+    /// As of the latest transaction committed at or before this Unix timestamp (seconds).
+    Timestamp(i64),
+}
+
+/// A single assertion or retraction of a fact about an atom, as returned by `Database::history`.
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    /// The transaction that made this change.
+    pub tx_id: i64,
+    /// When that transaction was committed (Unix timestamp, seconds).
+    pub timestamp: i64,
+    /// The table the changed fact lives in.
+    pub table: Table,
+    /// Whether this entry is the fact's assertion or its retraction.
+    pub retracted: bool,
+}
+
+/// Opens a new transaction, stamping it with `timestamp`, and returns its `tx_id`.
+///
+/// Every assertion/retraction a mutating handler makes should be grouped under one `tx_id` from
+/// a single call to this function, so they're all visible (or not) together under `AsOf::Tx`.
+fn begin_tx(conn: &Connection, timestamp: i64) -> rusqlite::Result<i64> {
+    conn.execute(
+        "insert into transactions (timestamp) values (?)",
+        params![timestamp],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Resolves an `AsOf` down to the `tx_id` boundary a read should filter against.
+fn resolve_as_of(conn: &Connection, as_of: AsOf) -> Result<i64> {
+    Ok(match as_of {
+        AsOf::Tx(tx_id) => tx_id,
+        AsOf::Timestamp(timestamp) => conn
+            .query_row(
+                "select max(tx_id) from transactions where timestamp <= ?",
+                params![timestamp],
+                |row| row.get::<_, Option<i64>>(0),
+            )?
+            .unwrap_or(0),
+    })
+}
+
+/// Returns the full assertion/retraction timeline of `atom`, across every fact table, ordered by
+/// transaction.
+fn history(conn: &Connection, atom: &str) -> Result<Vec<HistoryEntry>> {
+    let mut entries = Vec::new();
+    for (table, column) in &[
+        (Table::Names, "atom"),
+        (Table::Edges, "from"),
+        (Table::Tags, "atom"),
+        (Table::Blobs, "atom"),
+    ] {
+        let table_name = match table {
+            Table::Names => "names",
+            Table::Edges => "edges",
+            Table::Tags => "tags",
+            Table::Blobs => "blobs",
+        };
+        let mut stmt = conn.prepare(&format!(
+            "select tx_added, tx_retracted from {} where {} = ?",
+            table_name, column
+        ))?;
+        let mut rows = stmt.query(params![atom])?;
+        while let Some(row) = rows.next()? {
+            let tx_added: i64 = row.get(0)?;
+            entries.push(HistoryEntry {
+                tx_id: tx_added,
+                timestamp: tx_timestamp(conn, tx_added)?,
+                table: *table,
+                retracted: false,
+            });
+            if let Some(tx_retracted) = row.get::<_, Option<i64>>(1)? {
+                entries.push(HistoryEntry {
+                    tx_id: tx_retracted,
+                    timestamp: tx_timestamp(conn, tx_retracted)?,
+                    table: *table,
+                    retracted: true,
+                });
+            }
+        }
+    }
+    entries.sort_by_key(|entry| entry.tx_id);
+    Ok(entries)
+}
+
+fn tx_timestamp(conn: &Connection, tx_id: i64) -> Result<i64> {
+    conn.query_row(
+        "select timestamp from transactions where tx_id = ?",
+        params![tx_id],
+        |row| row.get(0),
+    )
+    .map_err(Into::into)
+}
+
+/// A table a query handler reads from or writes to, used to drive the result cache: a read
+/// handler's result is cached under the tables it reads, and a write handler invalidates every
+/// cache entry tagged with the tables it touches.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Table {
+    Names,
+    Edges,
+    Tags,
+    Blobs,
+}
+
+/// A per-predicate result cache living next to the `Connection` on the worker thread, so it
+/// needs no locking of its own.
+///
+/// Entries are keyed by the handler's name and its (debug-formatted) arguments, and tagged with
+/// the tables the handler reads from; `invalidate` drops every entry tagged with a given table.
+/// Results are type-erased since handlers return different `$outt`s -- `get_or_compute` downcasts
+/// back to the caller's concrete type, recomputing (and recaching) on a type mismatch, which can
+/// only happen if two handlers share a name.
+struct Cache {
+    entries: LruCache<(&'static str, String), (Box<dyn Any + Send>, Vec<Table>)>,
+}
+
+impl Cache {
+    fn new() -> Cache {
+        Cache {
+            entries: LruCache::new(256),
+        }
+    }
+
+    fn get_or_compute<T, F>(
+        &mut self,
+        name: &'static str,
+        key: String,
+        tables: Vec<Table>,
+        compute: F,
+    ) -> Result<T>
+    where
+        T: Clone + Send + 'static,
+        F: FnOnce() -> Result<T>,
+    {
+        if let Some((hit, _)) = self.entries.get(&(name, key.clone())) {
+            if let Some(value) = hit.downcast_ref::<T>() {
+                return Ok(value.clone());
+            }
+        }
+        let value = compute()?;
+        self.entries
+            .put((name, key), (Box::new(value.clone()), tables));
+        Ok(value)
+    }
+
+    /// Warms the cache by computing and storing the result of a read query ahead of time, e.g.
+    /// for a namespace or edge key that's about to be queried heavily.
+    fn warm<T, F>(&mut self, name: &'static str, key: String, tables: Vec<Table>, compute: F)
+    where
+        T: Clone + Send + 'static,
+        F: FnOnce() -> Result<T>,
+    {
+        if let Ok(value) = compute() {
+            self.entries
+                .put((name, key), (Box::new(value), tables));
+        }
+    }
+
+    /// Drops every cache entry tagged with `table`.
+    fn invalidate(&mut self, table: Table) {
+        let stale: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, tables))| tables.contains(&table))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            let _ = self.entries.pop(&key);
+        }
+    }
+
+    /// Drops every cache entry.
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// Each handler is tagged `read(Table, ...)` or `write(Table, ...)` with the tables it touches:
+// `read` handlers are served out of the `Cache` (falling back to `$body` on a miss), and `write`
+// handlers invalidate every cache entry tagged with their tables after `$body` succeeds.
 macro_rules! queries {
-    ($($(#[$meta:meta])* fn $name:ident(&$self:ident $(,$arg:ident : $argt:ty)*) -> $outt:ty $body:block)*) => {
+    ($($(#[$meta:meta])* $kind:ident($($table:ident),*) fn $name:ident(&$self:ident $(,$arg:ident : $argt:ty)*) -> $outt:ty $body:block)*) => {
         #[allow(non_camel_case_types)]
         enum Query {
             $($name($($argt,)* oneshot::Sender<Result<$outt>>),)*
@@ -19,27 +295,32 @@ macro_rules! queries {
 
         impl Database {
             $(
+                // NOTE: none of these bodies build a `TxReport` yet (they're all
+                // currently `unimplemented!()`/commented out below) -- once a mutating handler
+                // actually runs, it should return the set of changed rows so its `WorkerMsg::Query`
+                // arm can call `notify` with them.
                 $(#[$meta])*
                 pub async fn $name(&mut self, $($arg : $argt),*) -> Result<$outt> {
                     let (send, recv) = oneshot::channel();
                     let query = Query::$name($($arg,)* send);
-                    self.send.send(query)?;
+                    self.send.send(WorkerMsg::Query(query))?;
                     recv.await?
                 }
             )*
         }
 
         trait ConnectionExt {
-            fn handle_query(&self, query: Query);
+            fn handle_query(&self, cache: &mut Cache, query: Query);
 
             $(fn $name(&self, $($arg : $argt),*) -> Result<$outt>;)*
         }
 
         impl ConnectionExt for Connection {
-            fn handle_query(&self, query: Query) {
+            fn handle_query(&self, cache: &mut Cache, query: Query) {
                 match query {
                     $(Query::$name($($arg,)* send) => {
-                        drop(send.send(self.$name($($arg),*)));
+                        let result = queries!(@run self, cache, $kind, $name, [$($table),*], $($arg),*);
+                        drop(send.send(result));
                     },)*
                 }
             }
@@ -49,6 +330,22 @@ macro_rules! queries {
             })*
         }
     };
+
+    (@run $self:ident, $cache:ident, read, $name:ident, [$($table:ident),*], $($arg:ident),*) => {
+        $cache.get_or_compute(
+            stringify!($name),
+            format!("{:?}", ($($arg.clone(),)*)),
+            vec![$(Table::$table),*],
+            || $self.$name($($arg),*),
+        )
+    };
+    (@run $self:ident, $cache:ident, write, $name:ident, [$($table:ident),*], $($arg:ident),*) => {{
+        let result = $self.$name($($arg),*);
+        if result.is_ok() {
+            $($cache.invalidate(Table::$table);)*
+        }
+        result
+    }};
 }
 
 /// A connection to the database. Cheaply clonable.
@@ -56,7 +353,7 @@ macro_rules! queries {
 /// Since database operations are synchronous, holds open a separate thread for them.
 #[derive(Clone, Debug)]
 pub struct Database {
-    send: mpsc::SyncSender<Query>,
+    send: mpsc::SyncSender<WorkerMsg>,
     thread: Arc<JoinHandle<()>>,
 }
 
@@ -67,39 +364,110 @@ impl Database {
         let conn = Connection::open(path)?;
         conn.execute_batch(
             r#"
+            create table if not exists transactions
+              ( tx_id integer primary key
+              , timestamp integer not null
+              );
             create table if not exists names
               ( atom text not null
               , namespace text not null
               , title text not null
-              , constraint nameUnique unique (namespace, title)
+              , tx_added integer not null references transactions(tx_id)
+              , tx_retracted integer references transactions(tx_id)
               );
+            create unique index if not exists nameUniqueLive on names(namespace, title)
+              where tx_retracted is null;
             create table if not exists edges
               ( from text not null
               , to text not null
               , key text not null
-              , constraint edgeUnique unique (from, to, key)
+              , tx_added integer not null references transactions(tx_id)
+              , tx_retracted integer references transactions(tx_id)
               );
+            create unique index if not exists edgeUniqueLive on edges(from, to, key)
+              where tx_retracted is null;
             create table if not exists tags
               ( atom text not null
               , kind text not null
               , value text not null
-              , constraint tagUnique unique (atom, kind)
+              , tx_added integer not null references transactions(tx_id)
+              , tx_retracted integer references transactions(tx_id)
               );
+            create unique index if not exists tagUniqueLive on tags(atom, kind)
+              where tx_retracted is null;
             create table if not exists blobs
               ( atom text not null
               , mime text not null
               , hash text not null
               , contents blob not null
-              , constraint blobUnique unique (atom, mime, hash) -- checking contents is slow
+              , tx_added integer not null references transactions(tx_id)
+              , tx_retracted integer references transactions(tx_id)
               );
+            create unique index if not exists blobUniqueLive on blobs(atom, mime, hash)
+              where tx_retracted is null; -- checking contents is slow
             "#,
         )?;
         let (send, recv) = mpsc::sync_channel(8);
         let thread = Arc::new(spawn(move || {
-            recv.into_iter().for_each(|query| conn.handle_query(query));
+            let mut observers = Vec::new();
+            let mut cache = Cache::new();
+            for msg in recv.into_iter() {
+                match msg {
+                    WorkerMsg::Query(query) => conn.handle_query(&mut cache, query),
+                    WorkerMsg::Observe(filter, send) => observers.push(Observer { filter, send }),
+                    WorkerMsg::ClearCache(send) => {
+                        cache.clear();
+                        drop(send.send(()));
+                    }
+                    WorkerMsg::ResolveAsOf(as_of, send) => {
+                        drop(send.send(resolve_as_of(&conn, as_of)));
+                    }
+                    WorkerMsg::History(atom, send) => {
+                        drop(send.send(history(&conn, &atom)));
+                    }
+                }
+            }
         }));
         Ok(Database { send, thread })
     }
+
+    /// Subscribes to a stream of `TxReport`s for mutations matching `filter`.
+    ///
+    /// The returned receiver yields a `TxReport` every time a mutating query's changes match
+    /// `filter`, instead of having to re-poll for updates.
+    pub async fn observe(&self, filter: ObserverFilter) -> Result<async_mpsc::Receiver<TxReport>> {
+        let (send, recv) = async_mpsc::channel(16);
+        self.send.send(WorkerMsg::Observe(filter, send))?;
+        Ok(recv)
+    }
+
+    /// Drops every cached query result.
+    pub async fn clear_cache(&self) -> Result<()> {
+        let (send, recv) = oneshot::channel();
+        self.send.send(WorkerMsg::ClearCache(send))?;
+        recv.await?;
+        Ok(())
+    }
+
+    /// Resolves `as_of` down to a `tx_id`, for reads that want a reproducible snapshot instead of
+    /// the live head.
+    ///
+    /// NOTE: no generated read handler threads this through to its `$body` yet -- the `queries!`
+    /// block below has no live handlers to thread it through (see the note on `notify`). This is
+    /// the seam a future handler's `where tx_added <= ? and (tx_retracted is null or tx_retracted > ?)`
+    /// filter should plug into.
+    pub async fn query_as_of(&self, as_of: AsOf) -> Result<i64> {
+        let (send, recv) = oneshot::channel();
+        self.send.send(WorkerMsg::ResolveAsOf(as_of, send))?;
+        recv.await?
+    }
+
+    /// Returns the full assertion/retraction timeline of `atom`, across every fact table.
+    pub async fn history(&self, atom: String) -> Result<Vec<HistoryEntry>> {
+        let (send, recv) = oneshot::channel();
+        self.send.send(WorkerMsg::History(atom, send))?;
+        recv.await?
+    }
 }
 
 queries! {
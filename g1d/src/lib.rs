@@ -0,0 +1,36 @@
+//! `g1d`: an embeddable, SQLite-backed graph store with a temporal schema, a per-predicate result
+//! cache, and observer pub/sub -- the building blocks a standalone G1 daemon binary would sit on
+//! top of.
+#![deny(
+    bad_style,
+    bare_trait_objects,
+    const_err,
+    dead_code,
+    improper_ctypes,
+    legacy_directory_ownership,
+    missing_debug_implementations,
+    missing_docs,
+    no_mangle_generic_items,
+    non_shorthand_field_patterns,
+    overflowing_literals,
+    path_statements,
+    patterns_in_fns_without_body,
+    plugin_as_library,
+    private_in_public,
+    safe_extern_statics,
+    trivial_numeric_casts,
+    unconditional_recursion,
+    unions_with_drop_fields,
+    unsafe_code,
+    unused,
+    unused_allocation,
+    unused_comparisons,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_parens,
+    unused_qualifications,
+    unused_results,
+    while_true
+)]
+
+pub mod db;